@@ -0,0 +1,268 @@
+use crate::blockchain::block_store::BlockStore;
+use crate::blockchain::block::Block;
+use crate::blockchain::blockchain::Blockchain;
+use crate::blockchain::genesis::GenesisConfig;
+use crate::blockchain::mining::mine;
+use crate::blockchain::snapshot::SnapshotStore;
+use crate::membership::Membership;
+use crate::raft::consensus::{apply_committed, Raft, Role};
+use crate::utils::logger::Logger;
+use crate::utils::messages::Message;
+use crate::utils::socket::Socket;
+use ed25519_dalek::SigningKey;
+
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Max amount of records in a block
+pub const MAX_RECORDS_PER_BLOCK: usize = 10;
+
+/// Max time a record waits in the pending list before being sealed
+const SEAL_WINDOW_MILLIS: u64 = 2000;
+
+/// Time between checks of the pending records
+const SEAL_CHECK_INTERVAL_MILLIS: u64 = 200;
+
+/// Responsible for creating the genesis block when the leader has no blockchain,
+/// for sealing the pending records of the leader into blocks,
+/// when there are enough records or the oldest one waited too long,
+/// mining them if the blockchain has a difficulty,
+/// for saving a snapshot every `SNAPSHOT_INTERVAL` blocks
+/// and for sending every new block to the other nodes.
+/// In raft mode the blocks are proposed to raft instead, one at a time,
+/// and appended once they are committed
+pub struct BlockSealer {
+    pub blockchain: Arc<RwLock<Blockchain>>,
+    pub store: Arc<BlockStore>,
+    pub snapshots: Arc<SnapshotStore>,
+    pub socket: Socket,
+    pub my_address: Arc<RwLock<String>>,
+    pub leader_addr: Arc<RwLock<Option<String>>>,
+    pub members: Arc<Membership>,
+    pub pending_cv: Arc<(Mutex<bool>, Condvar)>,
+    pub logger: Arc<Logger>,
+    pub alive: Arc<RwLock<bool>>,
+    pub signing_key: Arc<SigningKey>,
+    pub genesis_config: Option<GenesisConfig>,
+    pub raft: Option<Arc<Mutex<Raft>>>,
+}
+
+impl BlockSealer {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        blockchain: Arc<RwLock<Blockchain>>,
+        store: Arc<BlockStore>,
+        snapshots: Arc<SnapshotStore>,
+        socket: Socket,
+        my_address: Arc<RwLock<String>>,
+        leader_addr: Arc<RwLock<Option<String>>>,
+        members: Arc<Membership>,
+        pending_cv: Arc<(Mutex<bool>, Condvar)>,
+        logger: Arc<Logger>,
+        alive: Arc<RwLock<bool>>,
+        signing_key: Arc<SigningKey>,
+        genesis_config: Option<GenesisConfig>,
+        raft: Option<Arc<Mutex<Raft>>>,
+    ) -> Self {
+        BlockSealer {
+            blockchain,
+            store,
+            snapshots,
+            socket,
+            my_address,
+            leader_addr,
+            members,
+            pending_cv,
+            logger,
+            alive,
+            signing_key,
+            genesis_config,
+            raft,
+        }
+    }
+
+    /// Waits until a record is submitted or the check interval elapses
+    /// and seals every block that is ready while this node is the leader
+    pub fn run(&mut self) {
+        while *self.alive.read().unwrap() {
+            {
+                let (lock, cv) = &*self.pending_cv;
+                let guard = lock.lock().unwrap();
+                let timeout = Duration::from_millis(SEAL_CHECK_INTERVAL_MILLIS);
+                let (mut submitted, _) = cv
+                    .wait_timeout_while(guard, timeout, |&mut submitted| !submitted)
+                    .unwrap();
+                *submitted = false;
+            }
+
+            if self.i_am_leader() && !self.waiting_for_commit() {
+                self.create_genesis_if_needed();
+                self.seal_ready_blocks();
+            }
+        }
+    }
+
+    fn i_am_leader(&self) -> bool {
+        *self.leader_addr.read().unwrap() == Some((*self.my_address.read().unwrap()).clone())
+    }
+
+    /// In raft mode a new block is sealed only after the last one was committed
+    fn waiting_for_commit(&self) -> bool {
+        match &self.raft {
+            Some(raft) => raft.lock().unwrap().has_uncommitted(),
+            None => false,
+        }
+    }
+
+    /// The first leader creates the network with the configuration file,
+    /// or with itself as the only admin if there is no file
+    fn create_genesis_if_needed(&mut self) {
+        let mut block = {
+            let blockchain = self.blockchain.read().unwrap();
+            if blockchain.len() > 0 {
+                return;
+            }
+            let proposer = self.my_address.read().unwrap().clone();
+            let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
+            let config = match &self.genesis_config {
+                Some(config) => config.clone(),
+                None => GenesisConfig::new(
+                    format!("{}-{}", proposer, now.as_secs()),
+                    self.signing_key.verifying_key(),
+                ),
+            };
+            blockchain.seal_genesis(config, proposer, now, &self.signing_key)
+        };
+        if self.mine_block(&mut block) {
+            self.publish_block(&block);
+        }
+    }
+
+    /// Seals, mines, appends, saves and broadcasts blocks while the pending records are ready.
+    /// The records of a block that isn't added go back to the pending records,
+    /// the leader already told their submitters that they were accepted
+    fn seal_ready_blocks(&mut self) {
+        let window = Duration::from_millis(SEAL_WINDOW_MILLIS);
+        let proposer = self.my_address.read().unwrap().clone();
+        while !self.waiting_for_commit() {
+            let mut block = {
+                let mut blockchain = self.blockchain.write().unwrap();
+                // Los registros esperan a que exista el bloque genesis
+                if blockchain.len() == 0 || !blockchain.should_seal(MAX_RECORDS_PER_BLOCK, window) {
+                    return;
+                }
+                let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
+                let sealed = blockchain.seal_block(MAX_RECORDS_PER_BLOCK, proposer.clone(), now);
+                match sealed {
+                    Some(block) => block,
+                    None => return,
+                }
+            };
+            if !self.mine_block(&mut block) {
+                self.blockchain.write().unwrap().restore_pending(block.records);
+                return;
+            }
+            if !self.publish_block(&block) {
+                // Los registros que ya no se pueden ejecutar se descartan, si no
+                // el mismo bloque se volveria a rechazar
+                let mut blockchain = self.blockchain.write().unwrap();
+                blockchain.restore_pending(block.records);
+                let dropped = blockchain.drop_invalid_pending();
+                if dropped > 0 {
+                    self.logger.info(format!("{} pending records can't be executed anymore and were dropped", dropped));
+                }
+                return;
+            }
+        }
+    }
+
+    /// Mines the block if the blockchain has a difficulty.
+    /// Returns false if the mining was cancelled
+    fn mine_block(&self, block: &mut Block) -> bool {
+        if block.header.difficulty == 0 {
+            return true;
+        }
+        // Se mina sin tomar el lock de la blockchain, si otro nodo pasa
+        // a ser el lider se cancela
+        let start = Instant::now();
+        let stop = || !self.i_am_leader() || !*self.alive.read().unwrap();
+        match mine(block, stop) {
+            Some(attempts) => {
+                self.logger.info(format!(
+                    "Block {} mined with difficulty {} in {} attempts ({} ms)",
+                    block.header.height,
+                    block.header.difficulty,
+                    attempts,
+                    start.elapsed().as_millis()
+                ));
+                true
+            }
+            None => {
+                self.logger.info(format!(
+                    "Mining of block {} was cancelled",
+                    block.header.height
+                ));
+                false
+            }
+        }
+    }
+
+    /// Appends the block to the blockchain, saves it and sends it to the other nodes
+    /// (or proposes it to raft). Returns false if the block was rejected
+    fn publish_block(&mut self, block: &Block) -> bool {
+        if let Some(raft) = self.raft.clone() {
+            self.propose(&raft, block.clone());
+            return true;
+        }
+
+        {
+            let mut blockchain = self.blockchain.write().unwrap();
+            if let Err(err) = blockchain.append_block(block.clone()) {
+                self.logger.info(format!("Sealed block was discarded: {}", err));
+                return false;
+            }
+            if let Err(err) = self.store.append(block) {
+                self.logger.info(format!("Unable to save block: {}", err));
+            }
+            match self.snapshots.save_if_due(&blockchain) {
+                Ok(Some(height)) => self.logger.info(format!("Snapshot of block {} saved", height)),
+                Ok(None) => {}
+                Err(err) => self.logger.info(format!("Unable to save snapshot: {}", err)),
+            }
+        }
+
+        self.logger.info(format!(
+            "Block {} sealed with {} records",
+            block.header.height,
+            block.get_records_count()
+        ));
+        for node in self.members.members() {
+            self.socket
+                .send_to(Message::Block(Box::new(block.clone())), node);
+        }
+        true
+    }
+
+    /// Adds the block to the log of raft, it's appended when a majority of the nodes stored it.
+    /// If this node is not the leader anymore the block is discarded
+    /// and the submitters of its records get a nack
+    fn propose(&mut self, raft: &Mutex<Raft>, block: Block) {
+        let height = block.header.height;
+        let records = block.get_records_count();
+        let (messages, proposed) = {
+            let mut raft = raft.lock().unwrap();
+            let proposed = raft.role == Role::Leader;
+            let step = raft.propose(block, &self.blockchain.read().unwrap());
+            apply_committed(step.committed, &self.blockchain, &self.store, &self.snapshots, &self.logger);
+            (step.messages, proposed)
+        };
+        if proposed {
+            self.logger.info(format!("Block {} proposed with {} records", height, records));
+        } else {
+            self.logger.info(format!("Block {} was discarded, this node is not the leader anymore", height));
+        }
+        for (node, msg) in messages {
+            self.socket.send_to(msg, node);
+        }
+    }
+}
@@ -1,42 +1,144 @@
-use super::record::Record;
-extern crate blake2;
-use blake2::{Blake2b, Digest};
+use super::hash::{hash_bytes, BlockHash, HASH_SIZE};
+use super::merkle::{build_proof, merkle_root, MerkleProof};
+use super::genesis::GenesisConfig;
+use super::record::{Record, RecordData};
+use crate::encoder::{ByteReader, ByteWriter};
+use std::time::Duration;
+
+/// Version of the serialization of the blocks (`Block::encode`),
+/// it changes every time the format of a block or a record changes
+/// (and so every time the hashes change)
+pub const BLOCK_FORMAT_VERSION: u32 = 11;
+
+/// Metadata of a block, the hash of the block is calculated over it
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockHeader {
+    /// Version of the format of the block (`BLOCK_FORMAT_VERSION`)
+    pub version: u32,
+
+    /// Position of the block in the blockchain, starting at 0
+    pub height: u64,
+
+    /// The duration since EPOCH (when the block was sealed)
+    pub timestamp: Duration,
+
+    /// Address of the node (leader) that sealed the block
+    pub proposer: String,
+
+    /// The hash that connects the blocks together
+    pub prev_hash: Option<BlockHash>,
+
+    /// Root of the Merkle tree of the records
+    pub merkle_root: BlockHash,
+
+    /// Hash of the world state after executing the records of the block
+    /// (see `StateMachine::state_root`), used to detect nodes whose state
+    /// diverged and to verify snapshots
+    pub state_root: BlockHash,
+
+    /// Amount of records in the block
+    pub record_count: u32,
+
+    /// Leading zero bits the hash of the block must have (0 if there is no mining)
+    pub difficulty: u32,
+
+    /// Value changed while mining until the hash meets the difficulty
+    pub nonce: u64,
+}
 
 /// A single part of the blockchain that contains a list of student records
 #[derive(Clone, Debug)]
 pub struct Block {
+    /// The metadata of the block
+    pub header: BlockHeader,
+
     /// The list of all the records in the block
     pub(crate) records: Vec<Record>,
 
-    /// The hash that connects the blocks together
-    pub prev_hash: Option<String>,
-
     /// Hash of the current block
-    pub hash: Option<String>,
+    pub hash: Option<BlockHash>,
+}
+
+impl BlockHeader {
+    /// Canonical serialization of the header:
+    /// `[version: u32][height: u64][timestamp secs: u64][timestamp nanos: u32]
+    /// [proposer][prev hash flag: u8][prev hash][merkle root][state root][record count: u32]
+    /// [difficulty: u32][nonce: u64]`.
+    /// The hash of the block is defined over these bytes so they must never
+    /// change without changing `BLOCK_FORMAT_VERSION`
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.put_u32(self.version);
+        writer.put_u64(self.height);
+        writer.put_u64(self.timestamp.as_secs());
+        writer.put_u32(self.timestamp.subsec_nanos());
+        writer.put_str(&self.proposer);
+        encode_opt_hash(writer, &self.prev_hash);
+        writer.put_raw(self.merkle_root.as_bytes());
+        writer.put_raw(self.state_root.as_bytes());
+        writer.put_u32(self.record_count);
+        writer.put_u32(self.difficulty);
+        writer.put_u64(self.nonce);
+    }
+
+    /// Hash of the block with this header: the hash of its canonical serialization
+    pub fn hash(&self) -> BlockHash {
+        let mut writer = ByteWriter::new();
+        self.encode(&mut writer);
+        hash_bytes(&writer.into_bytes())
+    }
+
+    /// Reads a header written by `BlockHeader::encode`
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        let version = reader.get_u32()?;
+        let height = reader.get_u64()?;
+        let secs = reader.get_u64()?;
+        let nanos = reader.get_u32()?;
+        Ok(BlockHeader {
+            version,
+            height,
+            timestamp: Duration::new(secs, nanos),
+            proposer: reader.get_str()?,
+            prev_hash: decode_opt_hash(reader)?,
+            merkle_root: BlockHash::from_slice(reader.get_raw(HASH_SIZE)?)?,
+            state_root: BlockHash::from_slice(reader.get_raw(HASH_SIZE)?)?,
+            record_count: reader.get_u32()?,
+            difficulty: reader.get_u32()?,
+            nonce: reader.get_u64()?,
+        })
+    }
 }
 
 impl Block {
-    pub fn new(prev_hash: Option<String>) -> Self {
+    /// Creates an empty block at `height` sealed by `proposer` at `timestamp`.
+    /// The state root is set by the blockchain when the block is sealed
+    pub fn new(
+        height: u64,
+        prev_hash: Option<BlockHash>,
+        proposer: String,
+        timestamp: Duration,
+    ) -> Self {
         Block {
+            header: BlockHeader {
+                version: BLOCK_FORMAT_VERSION,
+                height,
+                timestamp,
+                proposer,
+                prev_hash,
+                merkle_root: merkle_root(&[]),
+                state_root: hash_bytes(&[]),
+                record_count: 0,
+                difficulty: 0,
+                nonce: 0,
+            },
             hash: None,
-            prev_hash,
             records: Vec::new(),
         }
     }
 
-    /// Calculates the hash of the whole block using all the records
-    /// of the block and the block itself
-    pub fn calculate_hash(&self) -> Vec<u8> {
-        let mut hasher = Blake2b::new();
-
-        for record in self.records.iter() {
-            hasher.update(record.calculate_hash())
-        }
-
-        let block_as_string = format!("{:?}", (&self.prev_hash));
-        hasher.update(&block_as_string);
-
-        return Vec::from(hasher.finalize().as_ref());
+    /// Calculates the hash of the whole block using its header,
+    /// the records are covered by the Merkle root
+    pub fn calculate_hash(&self) -> BlockHash {
+        self.header.hash()
     }
 
     /// Appends a new record to the list
@@ -50,19 +152,135 @@ impl Block {
         self.records.len()
     }
 
-    /// Updates the current hash based on all the records and the block itself
+    /// Updates the header and the current hash based on all the records and the block itself
     pub(crate) fn update_hash(&mut self) {
-        self.hash = Some(byte_vector_to_string(&self.calculate_hash()));
+        self.header.merkle_root = merkle_root(&self.records);
+        self.header.record_count = self.records.len() as u32;
+        self.hash = Some(self.calculate_hash());
     }
 
     /// Checks if the hash is set and equals the internal calculated hash of the block
+    /// and if the header (Merkle root and record count) matches the records
     pub fn verify_own_hash(&self) -> bool {
-        return self.hash.is_some() && 
-                self.hash.as_ref().unwrap().eq(&byte_vector_to_string(&self.calculate_hash()));
+        self.header.record_count as usize == self.records.len()
+            && self.header.merkle_root == merkle_root(&self.records)
+            && self.hash == Some(self.calculate_hash())
+    }
+
+    /// Checks if the hash of the block has the leading zero bits required by its difficulty
+    pub fn meets_difficulty(&self) -> bool {
+        match self.hash {
+            Some(hash) => hash.leading_zero_bits() >= self.header.difficulty,
+            None => false,
+        }
+    }
+
+    /// Builds the proof that the record at `index` is part of this block,
+    /// it can be verified with `MerkleProof::verify` and the Merkle root of the block
+    pub fn inclusion_proof(&self, index: usize) -> Option<MerkleProof> {
+        build_proof(&self.records, index)
+    }
+
+    /// Configuration of the network if this is the genesis block
+    pub fn genesis_config(&self) -> Option<&GenesisConfig> {
+        match self.records.as_slice() {
+            [record] if self.header.height == 0 => match &record.record {
+                RecordData::Genesis(config) => Some(config),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Serializes the block keeping its hash as it is, so the receiver
+    /// can verify it instead of recalculating it
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        self.header.encode(writer);
+        encode_opt_hash(writer, &self.hash);
+        for record in self.records.iter() {
+            record.encode(writer);
+        }
+    }
+
+    /// Reads a block written by `Block::encode`
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        let header = BlockHeader::decode(reader)?;
+        let hash = decode_opt_hash(reader)?;
+        let mut records = Vec::new();
+        for _ in 0..header.record_count {
+            records.push(Record::decode(reader)?);
+        }
+        Ok(Block {
+            header,
+            records,
+            hash,
+        })
+    }
+}
+
+fn encode_opt_hash(writer: &mut ByteWriter, hash: &Option<BlockHash>) {
+    match hash {
+        Some(hash) => {
+            writer.put_u8(1);
+            writer.put_raw(hash.as_bytes());
+        }
+        None => writer.put_u8(0),
     }
 }
 
-/// Takes an array of bytes and returns a string by taking every byte as a character
-pub fn byte_vector_to_string(arr: &Vec<u8>) -> String {
-    arr.iter().map(|&c| c as char).collect()
+fn decode_opt_hash(reader: &mut ByteReader) -> Result<Option<BlockHash>, String> {
+    match reader.get_u8()? {
+        0 => Ok(None),
+        1 => Ok(Some(BlockHash::from_slice(reader.get_raw(HASH_SIZE)?)?)),
+        flag => Err(format!("Invalid option flag {}", flag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::record::RecordData;
+
+    /// Hashes of two linked blocks.
+    /// If this test fails the hashes of every stored blockchain changed:
+    /// bump `BLOCK_FORMAT_VERSION` and update the vectors
+    #[test]
+    fn block_hashes_are_stable() {
+        let mut first = Block::new(
+            0,
+            None,
+            "127.0.0.1:8000".into(),
+            Duration::new(1_600_000_002, 0),
+        );
+        first.add_record(Record::new(
+            "127.0.0.1:8000".into(),
+            RecordData::CreateStudent("100000".into(), 7),
+            Duration::new(1_600_000_000, 123_000_000),
+        ));
+        let mut second = Block::new(
+            1,
+            first.hash,
+            "127.0.0.1:8000".into(),
+            Duration::new(1_600_000_003, 500),
+        );
+        second.header.difficulty = 3;
+        second.header.nonce = 77;
+        second.header.state_root = hash_bytes(b"100000");
+        second.add_record(Record::new(
+            "127.0.0.1:8001".into(),
+            RecordData::AddSubjectGrade("100000".into(), "Concurrentes".into(), 10),
+            Duration::new(1_600_000_001, 0),
+        ));
+
+        assert_eq!(
+            first.hash.unwrap().to_string(),
+            "67312f74df1f68c97a250e187b4ba4247e69e50d209d731d857f08a89c88bba7\
+             b9ecfea64a2e71f1287246b3abf51974ce42f604e9754d8894e0e21707ac2a0c"
+        );
+        assert_eq!(
+            second.hash.unwrap().to_string(),
+            "ceee624b53fdc9a2d97b17d1056b166d018cfa24ae53e935a65feb5ed5225de7\
+             5a6484a469780ce5940ce8194b780cc5e0aa9bbfe10fe146bf3b519e2625e168"
+        );
+    }
 }
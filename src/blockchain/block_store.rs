@@ -0,0 +1,259 @@
+use super::block::{Block, BLOCK_FORMAT_VERSION};
+use super::hash::{hash_bytes, HASH_SIZE};
+use crate::encoder::{ByteReader, ByteWriter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+
+/// Size of the Blake2b digest used as checksum of every entry
+const CHECKSUM_SIZE: usize = HASH_SIZE;
+
+/// Identifies the file as a block store
+const STORE_MAGIC: &[u8; 4] = b"BCST";
+
+/// Size of the file header: magic and block format version
+const HEADER_SIZE: usize = 8;
+
+/// Append-only file with one block per entry.
+/// The file starts with `[magic][block format version: u32]`
+/// and each entry is `[payload length: u32][checksum][payload]`
+/// where the payload is the block serialized with `Block::encode`
+#[derive(Debug)]
+pub struct BlockStore {
+    filename: String,
+    file: Mutex<File>,
+}
+
+impl BlockStore {
+    /// Opens the store, creating the file if it doesn't exist
+    pub fn open(filename: &str) -> Result<Self, Error> {
+        Ok(BlockStore {
+            filename: filename.to_string(),
+            file: Mutex::new(open_file(filename)?),
+        })
+    }
+
+    /// Reads every stored block in order.
+    /// If the last entry is incomplete or its checksum doesn't match
+    /// (e.g. the node crashed while writing it) the file is truncated
+    /// up to the last valid entry so new blocks can be appended after it.
+    /// Returns the blocks and whether part of the file was discarded.
+    /// Fails if the file was written with another block format version or
+    /// if an entry followed by other entries is damaged, so they are not lost
+    pub fn load(&self) -> Result<(Vec<Block>, bool), Error> {
+        let mut file = self.file.lock().unwrap();
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut data)?;
+
+        if data.len() < HEADER_SIZE || data[..HEADER_SIZE] != store_header()[..] {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("not a block store of version {}", BLOCK_FORMAT_VERSION),
+            ));
+        }
+
+        let mut blocks = Vec::new();
+        let mut offset = HEADER_SIZE;
+        while offset < data.len() {
+            match read_entry(&data[offset..]) {
+                Ok((block, entry_size)) => {
+                    blocks.push(block);
+                    offset += entry_size;
+                }
+                // Solo una escritura cortada deja mal la ultima entrada
+                Err(entry_size) if offset + entry_size >= data.len() => break,
+                Err(_) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("the entry of block {} is damaged and is followed by more entries", blocks.len()),
+                    ))
+                }
+            }
+        }
+
+        let discarded = offset < data.len();
+        if discarded {
+            file.set_len(offset as u64)?;
+        }
+        file.seek(SeekFrom::End(0))?;
+        Ok((blocks, discarded))
+    }
+
+    /// Appends a block and waits until it reaches the disk
+    pub fn append(&self, block: &Block) -> Result<(), Error> {
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&build_entry(block))?;
+        file.sync_data()
+    }
+
+    /// Replaces the content of the store with the given blocks
+    /// (used when the whole blockchain is received from the leader).
+    /// The blocks are written in a temporary file that then replaces the old one,
+    /// so a crash leaves either the old blocks or the new ones
+    pub fn rewrite(&self, blocks: &[Block]) -> Result<(), Error> {
+        let mut file = self.file.lock().unwrap();
+        let mut data = store_header();
+        for block in blocks {
+            data.extend(build_entry(block));
+        }
+        let tmp_filename = format!("{}.tmp", self.filename);
+        let mut tmp_file = File::create(&tmp_filename)?;
+        tmp_file.write_all(&data)?;
+        tmp_file.sync_data()?;
+        fs::rename(tmp_filename, &self.filename)?;
+        *file = open_file(&self.filename)?;
+        Ok(())
+    }
+
+    /// Moves the file to `<filename>.bad` and starts an empty store,
+    /// used when the file can't be read so its blocks are not lost.
+    /// Returns the name of the moved file
+    pub fn set_aside(&self) -> Result<String, Error> {
+        let mut file = self.file.lock().unwrap();
+        let bad_filename = format!("{}.bad", self.filename);
+        fs::rename(&self.filename, &bad_filename)?;
+        *file = open_file(&self.filename)?;
+        Ok(bad_filename)
+    }
+}
+
+/// Opens the file of the store, writing the header if the file is new
+fn open_file(filename: &str) -> Result<File, Error> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(filename)?;
+    if file.metadata()?.len() == 0 {
+        file.write_all(&store_header())?;
+        file.sync_data()?;
+    }
+    Ok(file)
+}
+
+fn store_header() -> Vec<u8> {
+    let mut header = STORE_MAGIC.to_vec();
+    header.extend_from_slice(&BLOCK_FORMAT_VERSION.to_be_bytes());
+    header
+}
+
+/// Serializes a block as a store entry
+fn build_entry(block: &Block) -> Vec<u8> {
+    let mut writer = ByteWriter::new();
+    block.encode(&mut writer);
+    let payload = writer.into_bytes();
+
+    let mut entry = Vec::with_capacity(4 + CHECKSUM_SIZE + payload.len());
+    entry.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    entry.extend_from_slice(hash_bytes(&payload).as_bytes());
+    entry.extend(payload);
+    entry
+}
+
+/// Reads the entry at the start of `data`, returns the block and the size
+/// of the entry, or the size the entry claims to have if it's not valid
+fn read_entry(data: &[u8]) -> Result<(Block, usize), usize> {
+    if data.len() < 4 + CHECKSUM_SIZE {
+        return Err(data.len());
+    }
+    let mut len = [0; 4];
+    len.copy_from_slice(&data[..4]);
+    let len = u32::from_be_bytes(len) as usize;
+    let entry_size = 4 + CHECKSUM_SIZE + len;
+    if data.len() < entry_size {
+        return Err(entry_size);
+    }
+
+    let payload = &data[4 + CHECKSUM_SIZE..entry_size];
+    if hash_bytes(payload).as_bytes()[..] != data[4..4 + CHECKSUM_SIZE] {
+        return Err(entry_size);
+    }
+
+    let mut reader = ByteReader::new(payload);
+    match Block::decode(&mut reader) {
+        Ok(block) if reader.is_empty() => Ok((block, entry_size)),
+        _ => Err(entry_size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::record::{Record, RecordData};
+    use std::time::Duration;
+
+    fn block(height: u64, student: &str) -> Block {
+        let mut block = Block::new(height, None, "127.0.0.1:8000".into(), Duration::from_secs(height));
+        block.add_record(Record::new(
+            "127.0.0.1:8000".into(),
+            RecordData::CreateStudent(student.into(), 7),
+            Duration::from_secs(height),
+        ));
+        block
+    }
+
+    fn new_store(name: &str) -> (BlockStore, String) {
+        let filename = std::env::temp_dir().join(format!("block_store_test_{}", name));
+        let filename = filename.to_str().unwrap().to_string();
+        let _ = fs::remove_file(&filename);
+        (BlockStore::open(&filename).unwrap(), filename)
+    }
+
+    #[test]
+    fn an_incomplete_last_entry_is_discarded() {
+        let (store, filename) = new_store("truncated");
+        store.rewrite(&[block(0, "100000"), block(1, "100001")]).unwrap();
+        let size = fs::metadata(&filename).unwrap().len();
+        OpenOptions::new().write(true).open(&filename).unwrap().set_len(size - 10).unwrap();
+
+        let store = BlockStore::open(&filename).unwrap();
+        let (blocks, discarded) = store.load().unwrap();
+        assert!(discarded);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].hash, block(0, "100000").hash);
+
+        // Los bloques nuevos se agregan despues del ultimo valido
+        store.append(&block(1, "100002")).unwrap();
+        let (blocks, discarded) = store.load().unwrap();
+        assert!(!discarded);
+        assert_eq!(blocks.len(), 2);
+    }
+
+    #[test]
+    fn an_entry_with_a_wrong_checksum_is_discarded() {
+        let (store, filename) = new_store("checksum");
+        store.rewrite(&[block(0, "100000"), block(1, "100001")]).unwrap();
+        let mut data = fs::read(&filename).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        fs::write(&filename, data).unwrap();
+
+        let (blocks, discarded) = BlockStore::open(&filename).unwrap().load().unwrap();
+        assert!(discarded);
+        assert_eq!(blocks.len(), 1);
+    }
+
+    #[test]
+    fn a_damaged_entry_before_other_entries_is_not_discarded() {
+        let (store, filename) = new_store("middle");
+        store.rewrite(&[block(0, "100000"), block(1, "100001"), block(2, "100002")]).unwrap();
+        let mut data = fs::read(&filename).unwrap();
+        let second_entry = HEADER_SIZE + build_entry(&block(0, "100000")).len();
+        data[second_entry + 4 + CHECKSUM_SIZE] ^= 0xff;
+        fs::write(&filename, &data).unwrap();
+
+        let store = BlockStore::open(&filename).unwrap();
+        let err = store.load().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(fs::read(&filename).unwrap(), data);
+
+        // El archivo se guarda aparte con los bloques posteriores al danado
+        let moved = store.set_aside().unwrap();
+        assert_eq!(fs::read(&moved).unwrap(), data);
+        assert_eq!(store.load().unwrap().0.len(), 0);
+        fs::remove_file(moved).unwrap();
+    }
+}
@@ -1,75 +1,352 @@
-use super::block::Block;
-use super::record::Record;
-use super::student::Student;
-use std::collections::HashMap;
-use std::convert::Into;
+use super::block::{Block, BlockHeader, BLOCK_FORMAT_VERSION};
+use super::error::{BlockchainError, RecordError};
+use super::genesis::GenesisConfig;
+use super::hash::BlockHash;
+use super::record::{Record, RecordData};
+use super::snapshot::StateSnapshot;
+use super::state_machine::StateMachine;
+use super::student_registry::StudentRegistry;
+use ed25519_dalek::SigningKey;
 use std::fmt;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::time::{Duration, Instant};
 use std::vec::Vec;
 
-/// The Blockchain container
+/// Error found while validating a whole blockchain
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainValidationError {
+    /// Height of the first invalid block
+    pub block_index: usize,
+
+    /// Why the block is invalid
+    pub reason: BlockchainError,
+}
+
+/// The Blockchain container, it replicates the state of the application `S`
+/// (the student registry by default)
 #[derive(Debug, Clone)]
-pub struct Blockchain {
-    /// Blocks that are already in the blockchain
+pub struct Blockchain<S: StateMachine = StudentRegistry> {
+    /// Blocks that are already in the blockchain (after the snapshot, if there is one)
     pub blocks: Vec<Block>,
 
-    /// The world state
-    pub students: HashMap<String, Student>,
+    /// Snapshot the blocks start from, None if they start at the genesis block
+    base: Option<StateSnapshot<S>>,
+
+    /// The world state, the result of applying every record of the blocks in order
+    pub state: S,
+
+    /// Leading zero bits the hash of every block must have (0 disables mining)
+    pub difficulty: u32,
 
     /// Records that should be added to the chain but aren't yet
     pending_records: Vec<Record>,
+
+    /// When the oldest pending record was submitted
+    pending_since: Option<Instant>,
 }
 
-impl Blockchain {
-    /// Constructor
-    pub fn new() -> Self {
+impl<S: StateMachine> Blockchain<S> {
+    /// Creates an empty blockchain whose blocks must be mined with `difficulty`
+    /// (0 if there is no mining)
+    pub fn with_difficulty(difficulty: u32) -> Self {
         Blockchain {
             blocks: Vec::new(),
-            students: HashMap::new(),
+            base: None,
+            state: S::default(),
+            difficulty,
             pending_records: Vec::new(),
+            pending_since: None,
+        }
+    }
+
+    /// Builds a blockchain replaying the given blocks from the first one
+    pub fn from_blocks(blocks: Vec<Block>, difficulty: u32) -> Result<Self, ChainValidationError> {
+        Blockchain::replay(None, blocks, difficulty)
+    }
+
+    /// Builds a blockchain that starts with the world state of the snapshot
+    /// and replays only the blocks after it.
+    /// The snapshot is rejected if its state doesn't match the state root of its block,
+    /// if the block wasn't mined or if it's not of the network of `genesis_hash`
+    /// (None if the node doesn't know the genesis block yet)
+    pub fn from_snapshot(
+        snapshot: StateSnapshot<S>,
+        blocks: Vec<Block>,
+        difficulty: u32,
+        genesis_hash: Option<BlockHash>,
+    ) -> Result<Self, ChainValidationError> {
+        let block_index = snapshot.height() as usize;
+        if let Err(reason) = snapshot.verify(difficulty, genesis_hash) {
+            return Err(ChainValidationError { block_index, reason });
+        }
+        Blockchain::replay(Some(snapshot), blocks, difficulty)
+    }
+
+    fn replay(
+        base: Option<StateSnapshot<S>>,
+        blocks: Vec<Block>,
+        difficulty: u32,
+    ) -> Result<Self, ChainValidationError> {
+        let mut blockchain = Blockchain::with_difficulty(difficulty);
+        if let Some(base) = base {
+            blockchain.state = base.state.clone();
+            blockchain.base = Some(base);
+        }
+        for block in blocks {
+            let block_index = blockchain.len();
+            if let Err(reason) = blockchain.append_block(block) {
+                return Err(ChainValidationError { block_index, reason });
+            }
+        }
+        Ok(blockchain)
+    }
+
+    /// Verifies the whole blockchain replaying all the blocks into a fresh
+    /// world state (or into the state of its snapshot).
+    /// Returns the first block that can't be added and why
+    pub fn validate(&self) -> Result<(), ChainValidationError> {
+        let blocks = self.blocks.clone();
+        match &self.base {
+            Some(base) => {
+                Blockchain::from_snapshot(base.clone(), blocks, self.difficulty, Some(base.genesis_hash)).map(|_| ())
+            }
+            None => Blockchain::<S>::from_blocks(blocks, self.difficulty).map(|_| ()),
         }
     }
 
     /// Adds a block to the Blockchain
-    pub fn append_block(&mut self, block: Block) -> Result<(), String> {
-        // Checks if the hash matches the records
+    /// Checks the block and executes its records, the world state
+    /// is rolled back if any of the records fails or if the resulting
+    /// state doesn't match the state root of the block
+    pub fn append_block(&mut self, block: Block) -> Result<(), BlockchainError> {
+        if block.header.version != BLOCK_FORMAT_VERSION {
+            return Err(BlockchainError::UnsupportedVersion(block.header.version));
+        }
+
+        // Checks if the hash matches the header and the records
         if !block.verify_own_hash() {
-            return Err("The block hash is mismatching!".into());
+            return Err(BlockchainError::InvalidHash);
+        }
+
+        // Checks the proof of work
+        if block.header.difficulty != self.difficulty {
+            return Err(BlockchainError::InvalidDifficulty(
+                self.difficulty,
+                block.header.difficulty,
+            ));
+        }
+        if !block.meets_difficulty() {
+            return Err(BlockchainError::InsufficientWork);
+        }
+
+        // The height is the position of the block in the blockchain
+        let expected_height = self.len() as u64;
+        if block.header.height != expected_height {
+            return Err(BlockchainError::InvalidHeight(
+                expected_height,
+                block.header.height,
+            ));
         }
 
         // Checks if the new block has its previous hash equal to the hash of the last 
         // block of the blockchain
-        if !(block.prev_hash == self.get_last_block_hash()) {
-            return Err("The new block has to point to the previous block".into());
+        if block.header.prev_hash != self.get_last_block_hash() {
+            return Err(BlockchainError::BrokenLink);
+        }
+
+        // Blocks are sealed in order, so time can't go backwards
+        if let Some(last) = self.last_header() {
+            if block.header.timestamp < last.timestamp {
+                return Err(BlockchainError::InvalidTimestamp);
+            }
         }
 
         // An empty block cannot be added
         if block.get_records_count() == 0 {
-            return Err("There has to be at least one record inside the block".into());
+            return Err(BlockchainError::EmptyBlock);
+        }
+
+        // The first block only creates the network
+        if self.len() == 0 && !is_genesis_block(&block) {
+            return Err(BlockchainError::InvalidGenesis);
         }
 
         // Rollback if some records where right and others not
-        let old_state = self.students.clone();
+        let old_state = self.state.snapshot();
 
         // Executes each record
         for (i, record) in block.records.iter().enumerate() {
-            if let Err(err) = record.execute(self) {
-                self.students = old_state;
-                return Err(format!(
-                    "Could not execute record {} due to `{}`. Rolling back",
-                    i + 1,
-                    err
-                ));
+            if let Err(err) = self.state.apply(record) {
+                self.state.restore(old_state);
+                return Err(BlockchainError::InvalidRecord(i + 1, err));
             }
         }
+
+        // Every node must end with the same state as the node that sealed the block
+        if self.state.state_root() != block.header.state_root {
+            self.state.restore(old_state);
+            return Err(BlockchainError::InvalidStateRoot);
+        }
         self.blocks.push(block);
         Ok(())
     }
 
-    /// Returns the amount of blocks currently stored
+    /// Adds a record to the pending records if it can be executed
+    /// after the blockchain and the records that are already pending
+    pub fn submit_record(&mut self, record: Record) -> Result<(), RecordError> {
+        // Se aplican sobre el estado actual y despues se vuelve atras
+        let old_state = self.state.snapshot();
+        let state = &mut self.state;
+        let result = self
+            .pending_records
+            .iter()
+            .chain(std::iter::once(&record))
+            .try_for_each(|pending| state.apply(pending));
+        self.state.restore(old_state);
+        result?;
+
+        if self.pending_records.is_empty() {
+            self.pending_since = Some(Instant::now());
+        }
+        self.pending_records.push(record);
+        Ok(())
+    }
+
+    /// Drops the pending records that can't be executed anymore after the last block,
+    /// returns how many were dropped
+    pub fn drop_invalid_pending(&mut self) -> usize {
+        let old_state = self.state.snapshot();
+        let before = self.pending_records.len();
+        let state = &mut self.state;
+        self.pending_records.retain(|pending| state.apply(pending).is_ok());
+        self.state.restore(old_state);
+        if self.pending_records.is_empty() {
+            self.pending_since = None;
+        }
+        before - self.pending_records.len()
+    }
+
+    /// Puts back the records of a sealed block that wasn't added (its mining was cancelled
+    /// or it was rejected), before the records that were submitted after them
+    pub fn restore_pending(&mut self, records: Vec<Record>) {
+        if records.is_empty() {
+            return;
+        }
+        self.pending_records.splice(0..0, records);
+        self.pending_since.get_or_insert_with(Instant::now);
+    }
+
+    /// Returns the records waiting to be sealed in a block
+    pub fn get_pending_records(&self) -> &[Record] {
+        &self.pending_records
+    }
+
+    /// Checks if there are `max_records` pending records or if the oldest
+    /// one has been waiting for more than `window`
+    pub fn should_seal(&self, max_records: usize, window: Duration) -> bool {
+        match self.pending_since {
+            None => false,
+            Some(since) => self.pending_records.len() >= max_records || since.elapsed() >= window,
+        }
+    }
+
+    /// Takes up to `max_records` pending records (oldest first) and puts them
+    /// in a new block pointing to the last block, sealed by `proposer` at `now`
+    /// (or at the time of the last block if the clock went backwards).
+    /// The block is not appended and, if there is a difficulty, it still has to be mined
+    pub fn seal_block(
+        &mut self,
+        max_records: usize,
+        proposer: String,
+        now: Duration,
+    ) -> Option<Block> {
+        if self.pending_records.is_empty() || max_records == 0 {
+            return None;
+        }
+        let count = max_records.min(self.pending_records.len());
+        let timestamp = match self.last_header() {
+            Some(last) => now.max(last.timestamp),
+            None => now,
+        };
+        let mut block = Block::new(
+            self.len() as u64,
+            self.get_last_block_hash(),
+            proposer,
+            timestamp,
+        );
+        block.header.difficulty = self.difficulty;
+        for record in self.pending_records.drain(..count) {
+            block.add_record(record);
+        }
+        block.header.state_root = self.state_root_after(&block.records);
+        block.update_hash();
+
+        self.pending_since = if self.pending_records.is_empty() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+        Some(block)
+    }
+
+    /// Builds the first block of the blockchain, that creates the network with `config`.
+    /// The block is not appended and, if there is a difficulty, it still has to be mined
+    pub fn seal_genesis(&self, config: GenesisConfig, proposer: String, now: Duration, key: &SigningKey) -> Block {
+        let mut record = Record::new(proposer.clone(), RecordData::Genesis(config), now);
+        record.sign(key);
+        let mut state = S::default();
+        let _ = state.apply(&record);
+        let mut block = Block::new(0, None, proposer, now);
+        block.header.difficulty = self.difficulty;
+        block.header.state_root = state.state_root();
+        block.add_record(record);
+        block
+    }
+
+    /// Hash of the world state after executing `records`, the state doesn't change
+    fn state_root_after(&mut self, records: &[Record]) -> BlockHash {
+        let old_state = self.state.snapshot();
+        for record in records {
+            // Los registros pendientes ya se probaron en orden, un registro
+            // que falla igual hace que se rechace el bloque al agregarlo
+            let _ = self.state.apply(record);
+        }
+        let state_root = self.state.state_root();
+        self.state.restore(old_state);
+        state_root
+    }
+
+    /// Snapshot of the world state after the last block, None if there are no blocks
+    pub fn take_snapshot(&self) -> Option<StateSnapshot<S>> {
+        Some(StateSnapshot {
+            genesis_hash: self.get_genesis_hash()?,
+            header: self.last_header()?.clone(),
+            state: self.state.clone(),
+        })
+    }
+
+    /// Returns the hash of the genesis block, that identifies the network
+    pub fn get_genesis_hash(&self) -> Option<BlockHash> {
+        match &self.base {
+            Some(base) => Some(base.genesis_hash),
+            None => self.blocks.first().and_then(|block| block.hash),
+        }
+    }
+
+    /// Returns the amount of blocks of the blockchain,
+    /// counting the ones that are only in the snapshot
     pub fn len(&self) -> usize {
-        self.blocks.len()
+        self.first_height() as usize + self.blocks.len()
+    }
+
+    /// Height of the first block in `blocks`, the blocks before it
+    /// are only in the snapshot the blockchain started from
+    pub fn first_height(&self) -> u64 {
+        match &self.base {
+            Some(base) => base.height() + 1,
+            None => 0,
+        }
     }
 
     // Returns the block in the blockchain
@@ -77,22 +354,342 @@ impl Blockchain {
         self.blocks.clone()
     }
 
-    /// Returns the hash of the last block
-    pub fn get_last_block_hash(&self) -> Option<String> {
-        if self.len() == 0 {
+    /// Returns the block at `height`, None if it doesn't exist or it's only in the snapshot
+    pub fn get_block(&self, height: usize) -> Option<&Block> {
+        let first_height = self.first_height() as usize;
+        if height < first_height {
+            return None;
+        }
+        self.blocks.get(height - first_height)
+    }
+
+    /// Returns the hash of the block at `height`, also if it's the block of the snapshot.
+    /// None if it doesn't exist or it's before the snapshot
+    pub fn get_block_hash(&self, height: usize) -> Option<BlockHash> {
+        if height + 1 == self.first_height() as usize {
+            self.base.as_ref().map(|base| base.block_hash())
+        } else {
+            self.get_block(height).and_then(|block| block.hash)
+        }
+    }
+
+    /// Returns the blocks after the snapshot, None if the snapshot
+    /// isn't of a block of this blockchain
+    pub fn get_blocks_after(&self, snapshot: &StateSnapshot<S>) -> Option<Vec<Block>> {
+        let height = snapshot.height();
+        if self.get_block_hash(height as usize) != Some(snapshot.block_hash()) {
             return None;
         }
+        let blocks = self
+            .blocks
+            .iter()
+            .filter(|block| block.header.height > height)
+            .cloned()
+            .collect();
+        Some(blocks)
+    }
+
+    /// Returns the header of the last block
+    fn last_header(&self) -> Option<&BlockHeader> {
+        match self.blocks.last() {
+            Some(block) => Some(&block.header),
+            None => self.base.as_ref().map(|base| &base.header),
+        }
+    }
+
+    /// Returns the hash of the last block
+    pub fn get_last_block_hash(&self) -> Option<BlockHash> {
+        match self.blocks.last() {
+            Some(block) => block.hash,
+            None => self.base.as_ref().map(|base| base.block_hash()),
+        }
+    }
+}
+
+/// True if the only record of the block is a genesis record
+fn is_genesis_block(block: &Block) -> bool {
+    matches!(block.records.as_slice(), [record] if matches!(record.record, RecordData::Genesis(_)))
+}
 
-        self.blocks[self.len() - 1].hash.clone()
+impl<S: StateMachine + Display> Display for Blockchain<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.state)
     }
 }
 
-impl Display for Blockchain {
+impl Display for ChainValidationError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let mut data = format!("Blockchain:\n\tPadron\tNota");
-        for (padron, q) in &self.students {
-            data = format!("{}\n\t{}\t{}", data, padron, q.qualification);
+        write!(f, "Block {} is invalid: {}", self.block_index, self.reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::mining::{mine, MAX_DIFFICULTY};
+
+    const PROPOSER: &str = "127.0.0.1:8000";
+
+    /// Blockchain with only the genesis block, `key` is its admin
+    fn new_chain() -> (Blockchain, SigningKey) {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let mut blockchain = Blockchain::with_difficulty(0);
+        let config = GenesisConfig::new("test".into(), key.verifying_key());
+        let genesis = blockchain.seal_genesis(config, PROPOSER.into(), Duration::from_secs(1), &key);
+        blockchain.append_block(genesis).unwrap();
+        (blockchain, key)
+    }
+
+    fn record(key: &SigningKey, data: RecordData) -> Record {
+        let mut record = Record::new(PROPOSER.into(), data, Duration::from_secs(2));
+        record.sign(key);
+        record
+    }
+
+    fn create_student(key: &SigningKey, id: &str) -> Record {
+        record(key, RecordData::CreateStudent(id.into(), 7))
+    }
+
+    fn hashes(records: &[Record]) -> Vec<BlockHash> {
+        records.iter().map(|record| record.calculate_hash()).collect()
+    }
+
+    /// Adds a block with a new student for each id
+    fn append_students(blockchain: &mut Blockchain, key: &SigningKey, ids: &[&str]) {
+        for id in ids.iter() {
+            blockchain.submit_record(create_student(key, id)).unwrap();
+            let now = Duration::from_secs(blockchain.len() as u64 + 2);
+            let block = blockchain.seal_block(1, PROPOSER.into(), now).unwrap();
+            blockchain.append_block(block).unwrap();
         }
-        write!(f, "{}", data)
+    }
+
+    /// Error of the first invalid block found by `validate`
+    fn validation_error(blockchain: &Blockchain) -> (usize, BlockchainError) {
+        let err = blockchain.validate().unwrap_err();
+        (err.block_index, err.reason)
+    }
+
+    #[test]
+    fn a_cancelled_mine_keeps_its_records_pending() {
+        let (mut blockchain, key) = new_chain();
+        for id in ["100000", "100001", "100002"].iter() {
+            blockchain.submit_record(create_student(&key, id)).unwrap();
+        }
+        let submitted = hashes(blockchain.get_pending_records());
+
+        let mut block = blockchain.seal_block(2, PROPOSER.into(), Duration::from_secs(3)).unwrap();
+        assert_eq!(blockchain.get_pending_records().len(), 1);
+        block.header.difficulty = MAX_DIFFICULTY * 4;
+        assert_eq!(mine(&mut block, || true), None);
+
+        blockchain.restore_pending(block.records);
+        assert_eq!(hashes(blockchain.get_pending_records()), submitted);
+        assert!(blockchain.should_seal(3, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn pending_records_are_sealed_by_count_or_by_age() {
+        let (mut blockchain, key) = new_chain();
+        assert!(!blockchain.should_seal(1, Duration::from_secs(0)));
+
+        blockchain.submit_record(create_student(&key, "100000")).unwrap();
+        assert!(!blockchain.should_seal(2, Duration::from_secs(60)));
+        assert!(blockchain.should_seal(2, Duration::from_secs(0)));
+
+        blockchain.submit_record(create_student(&key, "100001")).unwrap();
+        assert!(blockchain.should_seal(2, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn blocks_are_sealed_with_the_oldest_pending_records() {
+        let (mut blockchain, key) = new_chain();
+        let records: Vec<Record> = ["100000", "100001", "100002"]
+            .iter()
+            .map(|id| create_student(&key, id))
+            .collect();
+        for record in records.iter() {
+            blockchain.submit_record(record.clone()).unwrap();
+        }
+
+        let block = blockchain.seal_block(2, PROPOSER.into(), Duration::from_secs(3)).unwrap();
+        assert_eq!(hashes(&block.records), hashes(&records[..2]));
+        assert_eq!(hashes(blockchain.get_pending_records()), hashes(&records[2..]));
+        blockchain.append_block(block).unwrap();
+
+        let block = blockchain.seal_block(2, PROPOSER.into(), Duration::from_secs(4)).unwrap();
+        assert_eq!(hashes(&block.records), hashes(&records[2..]));
+        assert!(blockchain.get_pending_records().is_empty());
+        assert!(!blockchain.should_seal(1, Duration::from_secs(0)));
+        blockchain.append_block(block).unwrap();
+        assert!(blockchain.seal_block(2, PROPOSER.into(), Duration::from_secs(5)).is_none());
+    }
+
+    #[test]
+    fn invalid_records_are_not_kept_pending() {
+        let (mut blockchain, key) = new_chain();
+        blockchain.submit_record(create_student(&key, "100000")).unwrap();
+        assert_eq!(
+            blockchain.submit_record(create_student(&key, "100000")),
+            Err(RecordError::DuplicateStudent("100000".into()))
+        );
+        let data = RecordData::CreateStudent("100001".into(), 7);
+        let mut unsigned = Record::new(PROPOSER.into(), data, Duration::from_secs(2));
+        assert_eq!(blockchain.submit_record(unsigned.clone()), Err(RecordError::MissingSignature));
+        unsigned.sign(&SigningKey::from_bytes(&[2; 32]));
+        assert_eq!(blockchain.submit_record(unsigned), Err(RecordError::Unauthorized(None)));
+        assert_eq!(blockchain.get_pending_records().len(), 1);
+
+        // Un bloque de otro lider crea el mismo alumno, el registro pendiente ya no se puede ejecutar
+        let mut other = new_chain().0;
+        other.submit_record(create_student(&key, "100000")).unwrap();
+        let block = other.seal_block(1, PROPOSER.into(), Duration::from_secs(3)).unwrap();
+        blockchain.append_block(block).unwrap();
+        assert_eq!(blockchain.drop_invalid_pending(), 1);
+        assert!(blockchain.get_pending_records().is_empty());
+        assert!(!blockchain.should_seal(1, Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn validate_reports_the_first_tampered_block() {
+        let (mut blockchain, key) = new_chain();
+        append_students(&mut blockchain, &key, &["100000", "100001", "100002"]);
+        assert_eq!(blockchain.validate(), Ok(()));
+
+        let mut tampered = blockchain.clone();
+        tampered.blocks[2].records[0].record = RecordData::CreateStudent("100001".into(), 10);
+        assert_eq!(validation_error(&tampered), (2, BlockchainError::InvalidHash));
+
+        // Con el hash recalculado el bloque es valido pero el siguiente ya no apunta a el
+        let mut tampered = blockchain.clone();
+        tampered.blocks[2].header.timestamp = Duration::from_secs(3);
+        tampered.blocks[2].update_hash();
+        assert_eq!(validation_error(&tampered), (3, BlockchainError::BrokenLink));
+
+        let mut tampered = blockchain.clone();
+        tampered.blocks[2].records.clear();
+        tampered.blocks[2].update_hash();
+        assert_eq!(validation_error(&tampered), (2, BlockchainError::EmptyBlock));
+
+        let mut tampered = blockchain.clone();
+        tampered.blocks[2].records[0] = create_student(&key, "100000");
+        tampered.blocks[2].update_hash();
+        assert_eq!(
+            validation_error(&tampered),
+            (2, BlockchainError::InvalidRecord(1, RecordError::DuplicateStudent("100000".into())))
+        );
+
+        let mut tampered = blockchain.clone();
+        tampered.blocks.remove(1);
+        assert_eq!(validation_error(&tampered), (1, BlockchainError::InvalidHeight(1, 2)));
+    }
+
+    /// Seals the next block with a new student and lets `tamper` change it before appending it,
+    /// the blockchain must stay as it was
+    fn append_tampered(tamper: impl FnOnce(&mut Blockchain, &mut Block)) -> BlockchainError {
+        let (mut blockchain, key) = new_chain();
+        blockchain.submit_record(create_student(&key, "100000")).unwrap();
+        let mut block = blockchain.seal_block(1, PROPOSER.into(), Duration::from_secs(3)).unwrap();
+        tamper(&mut blockchain, &mut block);
+        let state_root = blockchain.state.state_root();
+        let err = blockchain.append_block(block).unwrap_err();
+        assert_eq!(blockchain.len(), 1);
+        assert_eq!(blockchain.state.state_root(), state_root);
+        err
+    }
+
+    #[test]
+    fn append_block_rejects_every_invalid_block() {
+        assert_eq!(
+            append_tampered(|_, block| block.header.proposer = "127.0.0.1:8001".into()),
+            BlockchainError::InvalidHash
+        );
+        assert_eq!(
+            append_tampered(|_, block| {
+                block.header.version += 1;
+                block.update_hash();
+            }),
+            BlockchainError::UnsupportedVersion(BLOCK_FORMAT_VERSION + 1)
+        );
+        assert_eq!(
+            append_tampered(|_, block| {
+                block.header.difficulty = 1;
+                block.update_hash();
+            }),
+            BlockchainError::InvalidDifficulty(0, 1)
+        );
+        assert_eq!(
+            append_tampered(|blockchain, block| {
+                // Sin minar es casi imposible que el hash empiece con 32 bits en cero
+                blockchain.difficulty = MAX_DIFFICULTY;
+                block.header.difficulty = MAX_DIFFICULTY;
+                block.update_hash();
+            }),
+            BlockchainError::InsufficientWork
+        );
+        assert_eq!(
+            append_tampered(|_, block| {
+                block.header.height = 2;
+                block.update_hash();
+            }),
+            BlockchainError::InvalidHeight(1, 2)
+        );
+        assert_eq!(
+            append_tampered(|_, block| {
+                block.header.prev_hash = None;
+                block.update_hash();
+            }),
+            BlockchainError::BrokenLink
+        );
+        assert_eq!(
+            append_tampered(|_, block| {
+                block.header.timestamp = Duration::from_secs(0);
+                block.update_hash();
+            }),
+            BlockchainError::InvalidTimestamp
+        );
+        assert_eq!(
+            append_tampered(|_, block| {
+                block.records.clear();
+                block.update_hash();
+            }),
+            BlockchainError::EmptyBlock
+        );
+        assert_eq!(
+            append_tampered(|_, block| {
+                let stranger = SigningKey::from_bytes(&[2; 32]);
+                block.records.push(create_student(&stranger, "100001"));
+                block.update_hash();
+            }),
+            BlockchainError::InvalidRecord(2, RecordError::Unauthorized(None))
+        );
+        assert_eq!(
+            append_tampered(|blockchain, block| {
+                block.header.state_root = blockchain.state.state_root();
+                block.update_hash();
+            }),
+            BlockchainError::InvalidStateRoot
+        );
+    }
+
+    #[test]
+    fn the_first_block_must_be_a_genesis_block() {
+        let (chain, key) = new_chain();
+        let mut blockchain: Blockchain = Blockchain::with_difficulty(0);
+        let mut block = Block::new(0, None, PROPOSER.into(), Duration::from_secs(1));
+        block.add_record(create_student(&key, "100000"));
+        assert_eq!(blockchain.append_block(block), Err(BlockchainError::InvalidGenesis));
+        assert_eq!(blockchain.len(), 0);
+
+        let genesis = chain.blocks[0].clone();
+        blockchain.append_block(genesis.clone()).unwrap();
+        let mut second = genesis;
+        second.header.height = 1;
+        second.header.prev_hash = blockchain.get_last_block_hash();
+        second.update_hash();
+        assert_eq!(
+            blockchain.append_block(second),
+            Err(BlockchainError::InvalidRecord(1, RecordError::ChainAlreadyCreated))
+        );
     }
 }
@@ -0,0 +1,156 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// Reason why a record can't be executed on the world state
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordError {
+    /// The qualification or grade is out of the range of the network (qualification, min, max)
+    InvalidQualification(i32, i32, i32),
+
+    /// There is already a student with the id
+    DuplicateStudent(String),
+
+    /// There is no student with the id
+    UnknownStudent(String),
+
+    /// The student already has a grade for the subject (student id, subject)
+    SubjectAlreadyGraded(String, String),
+
+    /// The record wasn't signed by its submitter
+    MissingSignature,
+
+    /// The signature doesn't match the content of the record or its public key
+    InvalidSignature,
+
+    /// The signer can't write grades of the subject, or change students if None
+    Unauthorized(Option<String>),
+
+    /// Only an admin can add admins and grant or revoke permissions
+    NotAnAdmin,
+
+    /// The key doesn't have the permission that should be revoked
+    PermissionNotFound,
+
+    /// The network was already created by the genesis block
+    ChainAlreadyCreated,
+}
+
+/// Reason why a block can't be added to the blockchain
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockchainError {
+    /// The hash of the block doesn't match its content
+    InvalidHash,
+
+    /// The block doesn't point to the last block of the blockchain
+    BrokenLink,
+
+    /// The block has no records
+    EmptyBlock,
+
+    /// The first block has to have only the genesis record
+    InvalidGenesis,
+
+    /// The block was built with another format version
+    UnsupportedVersion(u32),
+
+    /// The height of the block isn't the next one of the blockchain (expected, got)
+    InvalidHeight(u64, u64),
+
+    /// The block was sealed before the last block of the blockchain
+    InvalidTimestamp,
+
+    /// The block wasn't mined with the difficulty of the blockchain (expected, got)
+    InvalidDifficulty(u32, u32),
+
+    /// The hash of the block doesn't have the leading zero bits of its difficulty
+    InsufficientWork,
+
+    /// A record of the block couldn't be executed (position of the record starting at 1)
+    InvalidRecord(usize, RecordError),
+
+    /// The world state doesn't match the state root of the block
+    InvalidStateRoot,
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::InvalidQualification(qualification, min, max) => write!(
+                f,
+                "Qualification must be between {} and {} (got {})",
+                min, max, qualification
+            ),
+            RecordError::DuplicateStudent(id) => write!(f, "The student {} already exists", id),
+            RecordError::UnknownStudent(id) => write!(f, "The student {} doesn't exist", id),
+            RecordError::SubjectAlreadyGraded(id, subject) => {
+                write!(f, "The subject {} of {} was already graded", subject, id)
+            }
+            RecordError::MissingSignature => write!(f, "The record is not signed"),
+            RecordError::InvalidSignature => write!(f, "The signature of the record is invalid"),
+            RecordError::Unauthorized(Some(subject)) => {
+                write!(f, "The signer is not allowed to grade {}", subject)
+            }
+            RecordError::Unauthorized(None) => {
+                write!(f, "The signer is not allowed to change students")
+            }
+            RecordError::NotAnAdmin => write!(f, "Only an admin can change the permissions"),
+            RecordError::PermissionNotFound => write!(f, "The key doesn't have that permission"),
+            RecordError::ChainAlreadyCreated => write!(f, "The chain already has a genesis block"),
+        }
+    }
+}
+
+impl Error for RecordError {}
+
+impl Display for BlockchainError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockchainError::InvalidHash => write!(f, "The block hash is mismatching!"),
+            BlockchainError::BrokenLink => {
+                write!(f, "The new block has to point to the previous block")
+            }
+            BlockchainError::EmptyBlock => {
+                write!(f, "There has to be at least one record inside the block")
+            }
+            BlockchainError::InvalidGenesis => {
+                write!(f, "The first block has to contain only the genesis record")
+            }
+            BlockchainError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported block format version {}", version)
+            }
+            BlockchainError::InvalidHeight(expected, got) => write!(
+                f,
+                "The block height has to be {} (got {})",
+                expected, got
+            ),
+            BlockchainError::InvalidTimestamp => {
+                write!(f, "The block can't be older than the previous block")
+            }
+            BlockchainError::InvalidDifficulty(expected, got) => write!(
+                f,
+                "The block difficulty has to be {} (got {})",
+                expected, got
+            ),
+            BlockchainError::InsufficientWork => {
+                write!(f, "The block hash doesn't meet its difficulty")
+            }
+            BlockchainError::InvalidRecord(i, err) => {
+                write!(f, "Could not execute record {} due to `{}`. Rolling back", i, err)
+            }
+            BlockchainError::InvalidStateRoot => {
+                write!(f, "The world state doesn't match the state root of the block")
+            }
+        }
+    }
+}
+
+impl Error for BlockchainError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BlockchainError::InvalidRecord(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
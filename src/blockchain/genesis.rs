@@ -0,0 +1,176 @@
+use super::signature::{parse_public_key, public_key_to_hex, read_public_key};
+use crate::encoder::{ByteReader, ByteWriter};
+use ed25519_dalek::VerifyingKey;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fs;
+
+/// Valid range for qualifications and subject grades when the configuration doesn't say
+pub const DEFAULT_MIN_QUALIFICATION: i32 = 1;
+pub const DEFAULT_MAX_QUALIFICATION: i32 = 10;
+
+/// Configuration of the network, stored in the only record of the first block.
+/// Two blockchains with different genesis blocks belong to different networks
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenesisConfig {
+    /// Name of the network
+    pub chain_id: String,
+
+    /// Lowest valid qualification and subject grade
+    pub min_qualification: i32,
+
+    /// Highest valid qualification and subject grade
+    pub max_qualification: i32,
+
+    /// Keys that are admins from the start
+    pub admins: Vec<VerifyingKey>,
+}
+
+impl GenesisConfig {
+    /// Configuration used by a leader that has no configuration file:
+    /// the default qualification range with the leader as the only admin
+    pub fn new(chain_id: String, admin: VerifyingKey) -> Self {
+        GenesisConfig {
+            chain_id,
+            min_qualification: DEFAULT_MIN_QUALIFICATION,
+            max_qualification: DEFAULT_MAX_QUALIFICATION,
+            admins: vec![admin],
+        }
+    }
+
+    /// Reads the configuration from a file with one `key = value` per line
+    /// (see `GenesisConfig::parse`)
+    pub fn from_file(filename: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(filename).map_err(|e| e.to_string())?;
+        GenesisConfig::parse(&content)
+    }
+
+    /// Parses a configuration like:
+    /// ```text
+    /// # comentario
+    /// chain_id = fiuba-concurrentes
+    /// min_qualification = 4
+    /// max_qualification = 10
+    /// admins = <public key in hex>, <public key in hex>
+    /// ```
+    /// `chain_id` and at least one admin are required, the rest of the keys are optional.
+    /// A network without admins couldn't be changed, nobody could grant permissions
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut chain_id = None;
+        let mut min_qualification = DEFAULT_MIN_QUALIFICATION;
+        let mut max_qualification = DEFAULT_MAX_QUALIFICATION;
+        let mut admins = Vec::new();
+
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format!("Line {} is not `key = value`", i + 1)),
+            };
+            let parse_number = |value: &str| {
+                value
+                    .parse::<i32>()
+                    .map_err(|_| format!("The {} must be a number", key))
+            };
+            match key {
+                "chain_id" => chain_id = Some(value.to_string()),
+                "min_qualification" => min_qualification = parse_number(value)?,
+                "max_qualification" => max_qualification = parse_number(value)?,
+                "admins" => {
+                    for admin in value.split(',').map(|a| a.trim()).filter(|a| !a.is_empty()) {
+                        admins.push(parse_public_key(admin)?);
+                    }
+                }
+                _ => return Err(format!("Unknown key {} in line {}", key, i + 1)),
+            }
+        }
+
+        let chain_id = match chain_id {
+            Some(chain_id) if !chain_id.is_empty() => chain_id,
+            _ => return Err("The chain_id is required".to_string()),
+        };
+        if admins.is_empty() {
+            return Err("At least one admin is required".to_string());
+        }
+        if min_qualification > max_qualification {
+            return Err("The min_qualification can't be greater than the max_qualification".to_string());
+        }
+        Ok(GenesisConfig {
+            chain_id,
+            min_qualification,
+            max_qualification,
+            admins,
+        })
+    }
+
+    /// Writes `[chain id][min: i32][max: i32][admin count: u32][admins]`
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.put_str(&self.chain_id);
+        writer.put_i32(self.min_qualification);
+        writer.put_i32(self.max_qualification);
+        writer.put_u32(self.admins.len() as u32);
+        for admin in self.admins.iter() {
+            writer.put_raw(admin.as_bytes());
+        }
+    }
+
+    /// Reads a configuration written by `GenesisConfig::encode`
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        let chain_id = reader.get_str()?;
+        let min_qualification = reader.get_i32()?;
+        let max_qualification = reader.get_i32()?;
+        let mut admins = Vec::new();
+        for _ in 0..reader.get_u32()? {
+            admins.push(read_public_key(reader)?);
+        }
+        Ok(GenesisConfig {
+            chain_id,
+            min_qualification,
+            max_qualification,
+            admins,
+        })
+    }
+}
+
+impl Display for GenesisConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Chain {} (qualifications from {} to {})",
+            self.chain_id, self.min_qualification, self.max_qualification
+        )?;
+        for admin in self.admins.iter() {
+            write!(f, "\n\tadmin {}", public_key_to_hex(admin))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn config_files_are_parsed() {
+        let admin = SigningKey::from_bytes(&[7; 32]).verifying_key();
+        let content = format!(
+            "# red de prueba\nchain_id = fiuba\nmin_qualification = 4\n\nadmins = {}\n",
+            public_key_to_hex(&admin)
+        );
+        let config = GenesisConfig::parse(&content).unwrap();
+
+        assert_eq!(config.chain_id, "fiuba");
+        assert_eq!((config.min_qualification, config.max_qualification), (4, 10));
+        assert_eq!(config.admins, vec![admin]);
+
+        assert!(GenesisConfig::parse("min_qualification = 4").is_err());
+        assert!(GenesisConfig::parse("chain_id = fiuba").is_err());
+        assert!(GenesisConfig::parse("chain_id = a\nmax_qualification = 0").is_err());
+        assert!(GenesisConfig::parse("chain_id = a\ncolor = blue").is_err());
+    }
+}
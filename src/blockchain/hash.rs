@@ -0,0 +1,84 @@
+use blake2::{Blake2b, Digest};
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// Size in bytes of a Blake2b digest
+pub const HASH_SIZE: usize = 64;
+
+/// Hash of a block (Blake2b digest), shown and parsed as hex
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockHash([u8; HASH_SIZE]);
+
+impl BlockHash {
+    /// Builds the hash from a digest, fails if it doesn't have `HASH_SIZE` bytes
+    pub fn from_slice(digest: &[u8]) -> Result<Self, String> {
+        if digest.len() != HASH_SIZE {
+            return Err(format!(
+                "A hash must have {} bytes (got {})",
+                HASH_SIZE,
+                digest.len()
+            ));
+        }
+        let mut bytes = [0; HASH_SIZE];
+        bytes.copy_from_slice(digest);
+        Ok(BlockHash(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; HASH_SIZE] {
+        &self.0
+    }
+
+    /// Amount of zero bits at the start of the hash (the work done to find it)
+    pub fn leading_zero_bits(&self) -> u32 {
+        let mut bits = 0;
+        for byte in self.0.iter() {
+            bits += byte.leading_zeros();
+            if *byte != 0 {
+                break;
+            }
+        }
+        bits
+    }
+}
+
+/// Calculates the Blake2b hash of the bytes
+pub fn hash_bytes(data: &[u8]) -> BlockHash {
+    let mut hasher = Blake2b::new();
+    hasher.update(data);
+    BlockHash::from_slice(hasher.finalize().as_ref()).unwrap()
+}
+
+impl Display for BlockHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for BlockHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "BlockHash({})", self)
+    }
+}
+
+impl FromStr for BlockHash {
+    type Err = String;
+
+    /// Parses a hash written as hex (as shown by `Display`)
+    fn from_str(hex: &str) -> Result<Self, Self::Err> {
+        if hex.len() != HASH_SIZE * 2 || !hex.is_ascii() {
+            return Err(format!("A hash must have {} hex digits", HASH_SIZE * 2));
+        }
+        let mut bytes = [0; HASH_SIZE];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| format!("Invalid hex digits at position {}", i * 2))?;
+        }
+        Ok(BlockHash(bytes))
+    }
+}
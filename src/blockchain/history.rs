@@ -0,0 +1,178 @@
+use super::blockchain::Blockchain;
+use super::hash::BlockHash;
+use super::record::RecordData;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::time::Duration;
+
+/// A record of the blockchain that changed a student
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    /// Client ID that submitted the record
+    pub from: String,
+
+    /// The duration since EPOCH (when the record was created)
+    pub created_at: Duration,
+
+    /// The operation performed on the student
+    pub operation: RecordData,
+
+    /// Height of the block that contains the record
+    pub block_index: usize,
+
+    /// Position of the record inside the block
+    pub record_index: usize,
+
+    /// Hash of the block that contains the record
+    pub block_hash: Option<BlockHash>,
+}
+
+impl Blockchain {
+    /// Returns every record that changed the student, oldest first, starting
+    /// at the record that created it: if the id was used by a removed student
+    /// before, the records of that student are not included.
+    /// If the student was transferred from another id the records
+    /// of the old id before the transfer are included too.
+    /// The records in blocks that are only in the snapshot are not included
+    pub fn get_student_history(&self, id: &str) -> Vec<HistoryEntry> {
+        // Se recorre de atras para adelante siguiendo el id que tenia el
+        // alumno en cada momento, hasta llegar al registro que lo creo
+        let mut current = id.to_string();
+        let mut history = Vec::new();
+        'blocks: for block in self.blocks.iter().rev() {
+            for (record_index, record) in block.records.iter().enumerate().rev() {
+                if !record.record.get_student_ids().contains(&&current) {
+                    continue;
+                }
+                history.push(HistoryEntry {
+                    from: record.from.clone(),
+                    created_at: record.created_at,
+                    operation: record.record.clone(),
+                    block_index: block.header.height as usize,
+                    record_index,
+                    block_hash: block.hash,
+                });
+                match &record.record {
+                    RecordData::CreateStudent(_, _) => break 'blocks,
+                    RecordData::TransferStudent(old_id, new_id) if *new_id == current => {
+                        current = old_id.clone();
+                    }
+                    _ => {}
+                }
+            }
+        }
+        history.reverse();
+        history
+    }
+}
+
+impl Display for HistoryEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let hash = match &self.block_hash {
+            Some(hash) => hash.to_string(),
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "[{}.{:03}] {} by {} (block {}: {})",
+            self.created_at.as_secs(),
+            self.created_at.subsec_millis(),
+            self.operation,
+            self.from,
+            self.block_index,
+            hash
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::genesis::GenesisConfig;
+    use crate::blockchain::record::Record;
+    use ed25519_dalek::SigningKey;
+
+    const PROPOSER: &str = "127.0.0.1:8000";
+
+    /// Blockchain with a block for each operation after the genesis block
+    fn chain_with(operations: Vec<RecordData>) -> Blockchain {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let mut blockchain = Blockchain::with_difficulty(0);
+        let config = GenesisConfig::new("test".into(), key.verifying_key());
+        let genesis = blockchain.seal_genesis(config, PROPOSER.into(), Duration::from_secs(1), &key);
+        blockchain.append_block(genesis).unwrap();
+        for (i, operation) in operations.into_iter().enumerate() {
+            let now = Duration::from_secs(i as u64 + 2);
+            let mut record = Record::new(PROPOSER.into(), operation, now);
+            record.sign(&key);
+            blockchain.submit_record(record).unwrap();
+            let block = blockchain.seal_block(1, PROPOSER.into(), now).unwrap();
+            blockchain.append_block(block).unwrap();
+        }
+        blockchain
+    }
+
+    fn operations(history: &[HistoryEntry]) -> Vec<RecordData> {
+        history.iter().map(|entry| entry.operation.clone()).collect()
+    }
+
+    #[test]
+    fn history_follows_the_student_through_its_transfers() {
+        let operations_of_a = vec![
+            RecordData::CreateStudent("100000".into(), 7),
+            RecordData::AddSubjectGrade("100000".into(), "Algebra".into(), 8),
+            RecordData::TransferStudent("100000".into(), "100001".into()),
+            RecordData::TransferStudent("100001".into(), "100002".into()),
+            RecordData::UpdateQualification("100002".into(), 9),
+        ];
+        let mut all = operations_of_a.clone();
+        // Otro alumno toma el id original despues de la transferencia
+        all.insert(3, RecordData::CreateStudent("100000".into(), 4));
+        all.push(RecordData::UpdateQualification("100000".into(), 5));
+        let blockchain = chain_with(all);
+
+        let history = blockchain.get_student_history("100002");
+        assert_eq!(operations(&history), operations_of_a);
+        assert_eq!(history[0].block_index, 1);
+        assert_eq!(history[4].block_index, 6);
+
+        assert_eq!(
+            operations(&blockchain.get_student_history("100000")),
+            vec![
+                RecordData::CreateStudent("100000".into(), 4),
+                RecordData::UpdateQualification("100000".into(), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn history_ignores_the_removed_students_with_the_same_id() {
+        let blockchain = chain_with(vec![
+            RecordData::CreateStudent("100000".into(), 7),
+            RecordData::AddSubjectGrade("100000".into(), "Algebra".into(), 8),
+            RecordData::RemoveStudent("100000".into()),
+            RecordData::CreateStudent("100001".into(), 6),
+            RecordData::RemoveStudent("100001".into()),
+            RecordData::CreateStudent("100000".into(), 9),
+            RecordData::TransferStudent("100000".into(), "100001".into()),
+        ]);
+
+        assert_eq!(
+            operations(&blockchain.get_student_history("100001")),
+            vec![
+                RecordData::CreateStudent("100000".into(), 9),
+                RecordData::TransferStudent("100000".into(), "100001".into()),
+            ]
+        );
+        // El id ya no existe, se muestra el ultimo alumno que lo tuvo
+        assert_eq!(
+            operations(&blockchain.get_student_history("100000")),
+            vec![
+                RecordData::CreateStudent("100000".into(), 9),
+                RecordData::TransferStudent("100000".into(), "100001".into()),
+            ]
+        );
+        assert!(blockchain.get_student_history("100009").is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+use super::hash::{hash_bytes, BlockHash};
+use super::record::Record;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// Prefix of the leaves of the tree, so a leaf can't be confused with an inner node
+const LEAF_PREFIX: u8 = 0;
+
+/// Prefix of the inner nodes of the tree, so an inner node can't be
+/// confused with the hash of a record
+const INNER_NODE_PREFIX: u8 = 1;
+
+/// Side of the sibling hash in a step of the proof
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// Proof that a record is one of the leaves of a Merkle tree:
+/// the sibling hashes needed to go from the record up to the root.
+/// The side of each sibling is given by the position of the record,
+/// so a proof can't claim that the record is at another position
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleProof {
+    /// Position of the record in the block
+    pub index: usize,
+
+    /// Amount of records in the block
+    pub count: usize,
+
+    /// Sibling of the node at every level that has one, from the leaf to the root
+    pub path: Vec<BlockHash>,
+}
+
+fn hash_leaf(record: &Record) -> BlockHash {
+    let mut data = vec![LEAF_PREFIX];
+    data.extend_from_slice(record.calculate_hash().as_bytes());
+    hash_bytes(&data)
+}
+
+fn hash_inner_node(left: &BlockHash, right: &BlockHash) -> BlockHash {
+    let mut data = vec![INNER_NODE_PREFIX];
+    data.extend_from_slice(left.as_bytes());
+    data.extend_from_slice(right.as_bytes());
+    hash_bytes(&data)
+}
+
+/// Builds the next level of the tree. When there is an odd amount of
+/// nodes the last one goes up as it is (it is not paired with itself)
+fn next_level(level: &[BlockHash]) -> Vec<BlockHash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => hash_inner_node(left, right),
+            [single] => *single,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Calculates the root of the Merkle tree whose leaves are the hashes
+/// of the records. The root of an empty tree is the hash of no bytes
+pub fn merkle_root(records: &[Record]) -> BlockHash {
+    let mut level: Vec<BlockHash> = records.iter().map(hash_leaf).collect();
+    if level.is_empty() {
+        return hash_bytes(&[]);
+    }
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Builds the proof of the record at `index`, None if there is no such record
+pub fn build_proof(records: &[Record], index: usize) -> Option<MerkleProof> {
+    if index >= records.len() {
+        return None;
+    }
+    let mut level: Vec<BlockHash> = records.iter().map(hash_leaf).collect();
+    let mut position = index;
+    let mut path = Vec::new();
+
+    while level.len() > 1 {
+        if let Some(sibling) = level.get(position ^ 1) {
+            path.push(*sibling);
+        }
+        level = next_level(&level);
+        position /= 2;
+    }
+    Some(MerkleProof {
+        index,
+        count: records.len(),
+        path,
+    })
+}
+
+impl MerkleProof {
+    /// Checks that the record is the leaf at `index` of the tree with the given root
+    pub fn verify(&self, record: &Record, merkle_root: &BlockHash) -> bool {
+        let steps = match self.steps() {
+            Some(steps) => steps,
+            None => return false,
+        };
+        let mut current = hash_leaf(record);
+        for (side, sibling) in steps {
+            current = match side {
+                Side::Left => hash_inner_node(sibling, &current),
+                Side::Right => hash_inner_node(&current, sibling),
+            };
+        }
+        current == *merkle_root
+    }
+
+    /// Pairs every sibling of the path with its side, following the position
+    /// of the record up the tree. None if the path doesn't fit a tree of `count` leaves
+    fn steps(&self) -> Option<Vec<(Side, &BlockHash)>> {
+        if self.index >= self.count {
+            return None;
+        }
+        let mut siblings = self.path.iter();
+        let mut steps = Vec::new();
+        let mut position = self.index;
+        let mut len = self.count;
+        while len > 1 {
+            // El ultimo nodo de un nivel impar sube sin hermano
+            if position ^ 1 < len {
+                let side = if position % 2 == 1 { Side::Left } else { Side::Right };
+                steps.push((side, siblings.next()?));
+            }
+            position /= 2;
+            len = len.div_ceil(2);
+        }
+        match siblings.next() {
+            Some(_) => None,
+            None => Some(steps),
+        }
+    }
+}
+
+impl Display for MerkleProof {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Record {} of {} of the block", self.index, self.count)?;
+        for (side, sibling) in self.steps().unwrap_or_default() {
+            let side = match side {
+                Side::Left => "L",
+                Side::Right => "R",
+            };
+            write!(f, "\n\t{} {}", side, sibling)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::record::RecordData;
+    use std::time::Duration;
+
+    fn records(count: usize) -> Vec<Record> {
+        (0..count)
+            .map(|i| {
+                Record::new(
+                    "127.0.0.1:8000".into(),
+                    RecordData::CreateStudent(format!("{}", 100000 + i), 7),
+                    Duration::from_secs(i as u64),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_record_has_a_valid_proof() {
+        for count in 1..10 {
+            let records = records(count);
+            let root = merkle_root(&records);
+            for (i, record) in records.iter().enumerate() {
+                let proof = build_proof(&records, i).unwrap();
+                assert!(proof.verify(record, &root));
+            }
+            assert!(build_proof(&records, count).is_none());
+        }
+    }
+
+    #[test]
+    fn proof_fails_for_another_record_or_root() {
+        let records = records(5);
+        let root = merkle_root(&records);
+        let proof = build_proof(&records, 2).unwrap();
+
+        assert!(!proof.verify(&records[3], &root));
+        assert!(!proof.verify(&records[2], &merkle_root(&records[..4])));
+    }
+
+    #[test]
+    fn proof_fails_for_another_position() {
+        let records = records(5);
+        let root = merkle_root(&records);
+        let proof = build_proof(&records, 2).unwrap();
+
+        let mut moved = proof.clone();
+        moved.index = 3;
+        assert!(!moved.verify(&records[2], &root));
+
+        let mut resized = proof.clone();
+        resized.count = 4;
+        assert!(!resized.verify(&records[2], &root));
+
+        let mut extended = proof;
+        extended.path.push(root);
+        assert!(!extended.verify(&records[2], &root));
+    }
+
+    #[test]
+    fn leaves_and_inner_nodes_have_different_hashes() {
+        let records = records(2);
+        assert_ne!(merkle_root(&records[..1]), records[0].calculate_hash());
+
+        // Las hojas se combinan con su prefijo, no con el hash del registro
+        let leaves: Vec<BlockHash> = records.iter().map(hash_leaf).collect();
+        let unprefixed = hash_inner_node(&records[0].calculate_hash(), &records[1].calculate_hash());
+        assert_ne!(merkle_root(&records), unprefixed);
+        assert_eq!(merkle_root(&records), hash_inner_node(&leaves[0], &leaves[1]));
+    }
+}
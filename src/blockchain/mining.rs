@@ -0,0 +1,66 @@
+use super::block::Block;
+
+/// Attempts between two checks of whether the mining has to stop
+const STOP_CHECK_INTERVAL: u64 = 1024;
+
+/// Highest difficulty accepted, more leading zero bits would take too long to mine
+pub const MAX_DIFFICULTY: u32 = 32;
+
+/// Tries nonces from 0 until the hash of the block meets its difficulty.
+/// Every `STOP_CHECK_INTERVAL` attempts `should_stop` is called and the mining
+/// is cancelled if it returns true (e.g. when another node became the leader).
+/// Returns the amount of hashes calculated, None if it was cancelled
+pub fn mine<F>(block: &mut Block, should_stop: F) -> Option<u64>
+where
+    F: Fn() -> bool,
+{
+    let mut attempts = 0;
+    block.header.nonce = 0;
+    loop {
+        block.update_hash();
+        attempts += 1;
+        if block.meets_difficulty() {
+            return Some(attempts);
+        }
+        if attempts % STOP_CHECK_INTERVAL == 0 && should_stop() {
+            return None;
+        }
+        block.header.nonce = block.header.nonce.wrapping_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::record::{Record, RecordData};
+    use std::time::Duration;
+
+    fn block(difficulty: u32) -> Block {
+        let mut block = Block::new(0, None, "127.0.0.1:8000".into(), Duration::from_secs(0));
+        block.header.difficulty = difficulty;
+        block.add_record(Record::new(
+            "127.0.0.1:8000".into(),
+            RecordData::CreateStudent("100000".into(), 7),
+            Duration::from_secs(0),
+        ));
+        block
+    }
+
+    #[test]
+    fn mined_blocks_meet_their_difficulty() {
+        for difficulty in 0..4 {
+            let mut block = block(difficulty);
+            assert!(mine(&mut block, || false).is_some());
+            assert!(block.verify_own_hash());
+            assert!(block.meets_difficulty());
+            assert!(block.hash.unwrap().leading_zero_bits() >= difficulty);
+        }
+    }
+
+    #[test]
+    fn mining_can_be_cancelled() {
+        let mut block = block(MAX_DIFFICULTY * 4);
+        assert_eq!(mine(&mut block, || true), None);
+        assert!(!block.meets_difficulty());
+    }
+}
@@ -1,5 +1,17 @@
 pub mod block;
+pub mod block_store;
 pub mod blockchain;
+pub mod error;
+pub mod genesis;
+pub mod hash;
+pub mod history;
+pub mod merkle;
+pub mod mining;
+pub mod permissions;
 pub mod record;
+pub mod signature;
+pub mod snapshot;
+pub mod state_machine;
 pub mod student;
+pub mod student_registry;
 pub mod world_state;
@@ -0,0 +1,196 @@
+use super::signature::{read_public_key, KeyFingerprint};
+use crate::encoder::{ByteReader, ByteWriter};
+use ed25519_dalek::VerifyingKey;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// What a submitter key is allowed to write
+#[derive(Clone, Debug, PartialEq)]
+pub enum Scope {
+    /// Any operation over any student and subject
+    AllSubjects,
+
+    /// Only grades of the given subjects
+    Subjects(BTreeSet<String>),
+}
+
+/// Part of the world state that says who can submit records.
+/// Admins manage the permissions and can submit any record,
+/// every other key needs a permission granted by an admin
+#[derive(Clone, Debug, Default)]
+pub struct Permissions {
+    /// Keys that can add admins and grant or revoke permissions
+    admins: HashSet<VerifyingKey>,
+
+    /// Permission of every authorized submitter key
+    grants: HashMap<VerifyingKey, Scope>,
+}
+
+impl Permissions {
+    pub fn is_admin(&self, key: &VerifyingKey) -> bool {
+        self.admins.contains(key)
+    }
+
+    /// Adds an admin, returns false if the key was already an admin
+    pub fn add_admin(&mut self, key: VerifyingKey) -> bool {
+        self.admins.insert(key)
+    }
+
+    /// Allows `key` to write grades of `subject`, or everything if `subject` is None
+    pub fn grant(&mut self, key: VerifyingKey, subject: Option<String>) {
+        match (self.grants.get_mut(&key), subject) {
+            (Some(Scope::AllSubjects), _) => {}
+            (Some(Scope::Subjects(subjects)), Some(subject)) => {
+                subjects.insert(subject);
+            }
+            (_, None) => {
+                self.grants.insert(key, Scope::AllSubjects);
+            }
+            (None, Some(subject)) => {
+                let subjects = std::iter::once(subject).collect();
+                self.grants.insert(key, Scope::Subjects(subjects));
+            }
+        }
+    }
+
+    /// Removes the permission of `key` over `subject`, or every permission of `key`
+    /// if `subject` is None. Returns false if there was no such permission
+    pub fn revoke(&mut self, key: &VerifyingKey, subject: Option<&str>) -> bool {
+        match (self.grants.get_mut(key), subject) {
+            (None, _) => false,
+            (Some(_), None) => self.grants.remove(key).is_some(),
+            (Some(Scope::AllSubjects), Some(_)) => false,
+            (Some(Scope::Subjects(subjects)), Some(subject)) => {
+                let removed = subjects.remove(subject);
+                if subjects.is_empty() {
+                    self.grants.remove(key);
+                }
+                removed
+            }
+        }
+    }
+
+    /// Checks if `key` can write grades of `subject`, or change
+    /// the students themselves if `subject` is None
+    pub fn allows(&self, key: &VerifyingKey, subject: Option<&str>) -> bool {
+        if self.is_admin(key) {
+            return true;
+        }
+        match (self.grants.get(key), subject) {
+            (Some(Scope::AllSubjects), _) => true,
+            (Some(Scope::Subjects(subjects)), Some(subject)) => subjects.contains(subject),
+            _ => false,
+        }
+    }
+
+    /// Writes `[admin count: u32][admins][grant count: u32]` and every
+    /// `[key][scope]`, the keys sorted by their bytes so the same
+    /// permissions are always written the same way.
+    /// The scope is `[0]` for all subjects or `[1][subject count: u32][subjects]`
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        let mut admins: Vec<&VerifyingKey> = self.admins.iter().collect();
+        admins.sort_by_key(|key| key.as_bytes());
+        writer.put_u32(admins.len() as u32);
+        for admin in admins {
+            writer.put_raw(admin.as_bytes());
+        }
+
+        let mut grants: Vec<(&VerifyingKey, &Scope)> = self.grants.iter().collect();
+        grants.sort_by_key(|(key, _)| key.as_bytes());
+        writer.put_u32(grants.len() as u32);
+        for (key, scope) in grants {
+            writer.put_raw(key.as_bytes());
+            match scope {
+                Scope::AllSubjects => writer.put_u8(0),
+                Scope::Subjects(subjects) => {
+                    writer.put_u8(1);
+                    writer.put_u32(subjects.len() as u32);
+                    for subject in subjects.iter() {
+                        writer.put_str(subject);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the permissions written by `Permissions::encode`
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        let mut permissions = Permissions::default();
+        for _ in 0..reader.get_u32()? {
+            permissions.admins.insert(read_public_key(reader)?);
+        }
+        for _ in 0..reader.get_u32()? {
+            let key = read_public_key(reader)?;
+            let scope = match reader.get_u8()? {
+                0 => Scope::AllSubjects,
+                1 => {
+                    let mut subjects = BTreeSet::new();
+                    for _ in 0..reader.get_u32()? {
+                        subjects.insert(reader.get_str()?);
+                    }
+                    Scope::Subjects(subjects)
+                }
+                tag => return Err(format!("Unknown permission scope {}", tag)),
+            };
+            permissions.grants.insert(key, scope);
+        }
+        Ok(permissions)
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::AllSubjects => write!(f, "all subjects"),
+            Scope::Subjects(subjects) => {
+                let subjects: Vec<&str> = subjects.iter().map(|s| s.as_str()).collect();
+                write!(f, "{}", subjects.join(", "))
+            }
+        }
+    }
+}
+
+impl Display for Permissions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Permissions:")?;
+        for admin in self.admins.iter() {
+            write!(f, "\n\t{}\tadmin", KeyFingerprint(admin))?;
+        }
+        for (key, scope) in self.grants.iter() {
+            write!(f, "\n\t{}\t{}", KeyFingerprint(key), scope)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    fn key(seed: u8) -> VerifyingKey {
+        SigningKey::from_bytes(&[seed; 32]).verifying_key()
+    }
+
+    #[test]
+    fn permissions_are_scoped_per_subject() {
+        let mut permissions = Permissions::default();
+        let (admin, professor) = (key(1), key(2));
+        permissions.add_admin(admin);
+        permissions.grant(professor, Some("Concurrentes".into()));
+
+        assert!(permissions.allows(&admin, None));
+        assert!(permissions.allows(&professor, Some("Concurrentes")));
+        assert!(!permissions.allows(&professor, Some("Algebra")));
+        assert!(!permissions.allows(&professor, None));
+        assert!(!permissions.allows(&key(3), Some("Concurrentes")));
+
+        permissions.grant(professor, None);
+        assert!(permissions.allows(&professor, Some("Algebra")));
+        assert!(!permissions.revoke(&professor, Some("Algebra")));
+        assert!(permissions.revoke(&professor, None));
+        assert!(!permissions.allows(&professor, Some("Concurrentes")));
+    }
+}
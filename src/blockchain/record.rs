@@ -1,5 +1,13 @@
+use super::error::RecordError;
+use super::genesis::GenesisConfig;
 use super::world_state::WorldState;
-use blake2::{Blake2b, Digest};
+use crate::encoder::{ByteReader, ByteWriter};
+use super::hash::{hash_bytes, BlockHash};
+use super::signature::{read_public_key, KeyFingerprint, RecordSignature};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
 use std::time::Duration;
 
 /// Request to the blockchain
@@ -13,38 +21,490 @@ pub struct Record {
 
     /// The type of the record and its additional information
     pub(crate) record: RecordData,
+
+    /// Key and signature of the submitter, None until the record is signed
+    pub signature: Option<RecordSignature>,
 }
 
 /// The operation to be stored on the blockchain
 #[derive(Clone, Debug, PartialEq)]
 pub enum RecordData {
-    CreateStudent(String, i32)
+    /// Registers a new student (id, qualification)
+    CreateStudent(String, i32),
+
+    /// Changes the qualification of an existing student (id, qualification)
+    UpdateQualification(String, i32),
+
+    /// Removes an existing student (id)
+    RemoveStudent(String),
+
+    /// Adds the grade of a subject to an existing student (id, subject, grade)
+    AddSubjectGrade(String, String, i32),
+
+    /// Moves a student with all its grades to a new id (id, new id)
+    TransferStudent(String, String),
+
+    /// Makes the key an admin, signed by an admin (the first ones come from the genesis block)
+    AddAdmin(VerifyingKey),
+
+    /// Allows the key to write grades of the subject (every operation if None)
+    GrantPermission(VerifyingKey, Option<String>),
+
+    /// Takes back the permission of the key over the subject (every permission if None)
+    RevokePermission(VerifyingKey, Option<String>),
+
+    /// Creates the network, it is the only record of the first block
+    Genesis(GenesisConfig),
 }
 
+impl RecordData {
+    /// Returns the ids of the students changed by the operation
+    pub fn get_student_ids(&self) -> Vec<&String> {
+        match self {
+            RecordData::CreateStudent(id, _) => vec![id],
+            RecordData::UpdateQualification(id, _) => vec![id],
+            RecordData::RemoveStudent(id) => vec![id],
+            RecordData::AddSubjectGrade(id, _, _) => vec![id],
+            RecordData::TransferStudent(id, new_id) => vec![id, new_id],
+            RecordData::AddAdmin(_)
+            | RecordData::GrantPermission(_, _)
+            | RecordData::RevokePermission(_, _)
+            | RecordData::Genesis(_) => vec![],
+        }
+    }
+}
+
+impl Display for RecordData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordData::CreateStudent(id, qualification) => {
+                write!(f, "Student {} created with qualification {}", id, qualification)
+            }
+            RecordData::UpdateQualification(id, qualification) => {
+                write!(f, "Qualification of {} changed to {}", id, qualification)
+            }
+            RecordData::RemoveStudent(id) => write!(f, "Student {} removed", id),
+            RecordData::AddSubjectGrade(id, subject, grade) => {
+                write!(f, "Subject {} of {} graded with {}", subject, id, grade)
+            }
+            RecordData::TransferStudent(id, new_id) => {
+                write!(f, "Student {} transferred to {}", id, new_id)
+            }
+            RecordData::AddAdmin(key) => write!(f, "Admin {} added", KeyFingerprint(key)),
+            RecordData::GrantPermission(key, subject) => write!(
+                f,
+                "Permission on {} granted to {}",
+                subject.as_deref().unwrap_or("all subjects"),
+                KeyFingerprint(key)
+            ),
+            RecordData::RevokePermission(key, subject) => write!(
+                f,
+                "Permission on {} revoked from {}",
+                subject.as_deref().unwrap_or("all subjects"),
+                KeyFingerprint(key)
+            ),
+            RecordData::Genesis(config) => write!(
+                f,
+                "Chain {} created with qualifications from {} to {} and {} admins",
+                config.chain_id,
+                config.min_qualification,
+                config.max_qualification,
+                config.admins.len()
+            ),
+        }
+    }
+}
+
+const CREATE_STUDENT_TAG: u8 = 1;
+const UPDATE_QUALIFICATION_TAG: u8 = 2;
+const REMOVE_STUDENT_TAG: u8 = 3;
+const ADD_SUBJECT_GRADE_TAG: u8 = 4;
+const TRANSFER_STUDENT_TAG: u8 = 5;
+const ADD_ADMIN_TAG: u8 = 6;
+const GRANT_PERMISSION_TAG: u8 = 7;
+const REVOKE_PERMISSION_TAG: u8 = 8;
+const GENESIS_TAG: u8 = 9;
+
 impl Record {
     pub fn new(from: String, record_data: RecordData, time: Duration) -> Self {
         Record {
             from,
             record: record_data,
             created_at: time,
+            signature: None,
         }
     }
 
-    /// Will change the world state
-    pub fn execute(&self, world_state: &mut dyn WorldState) -> Result<(), &'static str> {
+    /// Signs the record with the key of the node that submits it
+    pub fn sign(&mut self, key: &SigningKey) {
+        self.signature = Some(RecordSignature::sign(key, &self.signing_bytes()));
+    }
+
+    /// Checks that the record is signed and that the signature matches its content.
+    /// Returns the key of the signer
+    pub fn verify_signature(&self) -> Result<&VerifyingKey, RecordError> {
+        match &self.signature {
+            None => Err(RecordError::MissingSignature),
+            Some(signature) if !signature.verify(&self.signing_bytes()) => {
+                Err(RecordError::InvalidSignature)
+            }
+            Some(signature) => Ok(&signature.public_key),
+        }
+    }
+
+    /// Will change the world state, only if the signature of the record is valid
+    /// and the signer is allowed to submit it
+    pub fn execute(&self, world_state: &mut dyn WorldState) -> Result<(), RecordError> {
+        let signer = self.verify_signature()?;
         return match &self.record {
             RecordData::CreateStudent(id, qualification) => {
+                world_state.check_permission(signer, None)?;
                 world_state.create_student(id.into(), *qualification)
             }
+            RecordData::UpdateQualification(id, qualification) => {
+                world_state.check_permission(signer, None)?;
+                world_state.update_qualification(id, *qualification)
+            }
+            RecordData::RemoveStudent(id) => {
+                world_state.check_permission(signer, None)?;
+                world_state.remove_student(id)
+            }
+            RecordData::AddSubjectGrade(id, subject, grade) => {
+                world_state.check_permission(signer, Some(subject))?;
+                world_state.add_subject_grade(id, subject.into(), *grade)
+            }
+            RecordData::TransferStudent(id, new_id) => {
+                world_state.check_permission(signer, None)?;
+                world_state.transfer_student(id, new_id.into())
+            }
+            RecordData::AddAdmin(key) => world_state.add_admin(signer, *key),
+            RecordData::GrantPermission(key, subject) => {
+                world_state.grant_permission(signer, *key, subject.clone())
+            }
+            RecordData::RevokePermission(key, subject) => {
+                world_state.revoke_permission(signer, key, subject.as_deref())
+            }
+            RecordData::Genesis(config) => world_state.create_chain(config.clone()),
+        };
+    }
+
+    /// Calculates the hash using Blake2 hasher over the canonical bytes
+    pub fn calculate_hash(&self) -> BlockHash {
+        hash_bytes(&self.canonical_bytes())
+    }
+
+    /// Canonical serialization of the record (the same bytes written by `encode`).
+    /// The hash of the record is defined over these bytes so they must never
+    /// change without changing `BLOCK_FORMAT_VERSION`
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut writer = ByteWriter::new();
+        self.encode(&mut writer);
+        writer.into_bytes()
+    }
+
+    /// Bytes covered by the signature: every field but the signature itself
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut writer = ByteWriter::new();
+        self.encode_content(&mut writer);
+        writer.into_bytes()
+    }
+
+    /// Serializes the record so it can be stored or sent.
+    /// Every field is written in a fixed order, numbers are big endian and
+    /// strings are prefixed by their length (see `ByteWriter`).
+    /// The signature goes at the end: `[flag: u8][public key][signature]`
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        self.encode_content(writer);
+        match &self.signature {
+            Some(signature) => {
+                writer.put_u8(1);
+                signature.encode(writer);
+            }
+            None => writer.put_u8(0),
+        }
+    }
+
+    fn encode_content(&self, writer: &mut ByteWriter) {
+        writer.put_str(&self.from);
+        writer.put_u64(self.created_at.as_secs());
+        writer.put_u32(self.created_at.subsec_nanos());
+        match &self.record {
+            RecordData::CreateStudent(id, qualification) => {
+                writer.put_u8(CREATE_STUDENT_TAG);
+                writer.put_str(id);
+                writer.put_i32(*qualification);
+            }
+            RecordData::UpdateQualification(id, qualification) => {
+                writer.put_u8(UPDATE_QUALIFICATION_TAG);
+                writer.put_str(id);
+                writer.put_i32(*qualification);
+            }
+            RecordData::RemoveStudent(id) => {
+                writer.put_u8(REMOVE_STUDENT_TAG);
+                writer.put_str(id);
+            }
+            RecordData::AddSubjectGrade(id, subject, grade) => {
+                writer.put_u8(ADD_SUBJECT_GRADE_TAG);
+                writer.put_str(id);
+                writer.put_str(subject);
+                writer.put_i32(*grade);
+            }
+            RecordData::TransferStudent(id, new_id) => {
+                writer.put_u8(TRANSFER_STUDENT_TAG);
+                writer.put_str(id);
+                writer.put_str(new_id);
+            }
+            RecordData::AddAdmin(key) => {
+                writer.put_u8(ADD_ADMIN_TAG);
+                writer.put_raw(key.as_bytes());
+            }
+            RecordData::GrantPermission(key, subject) => {
+                writer.put_u8(GRANT_PERMISSION_TAG);
+                writer.put_raw(key.as_bytes());
+                encode_opt_str(writer, subject);
+            }
+            RecordData::RevokePermission(key, subject) => {
+                writer.put_u8(REVOKE_PERMISSION_TAG);
+                writer.put_raw(key.as_bytes());
+                encode_opt_str(writer, subject);
+            }
+            RecordData::Genesis(config) => {
+                writer.put_u8(GENESIS_TAG);
+                config.encode(writer);
+            }
+        }
+    }
+
+    /// Reads a record written by `Record::encode`
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        let from = reader.get_str()?;
+        let secs = reader.get_u64()?;
+        let nanos = reader.get_u32()?;
+        let record = match reader.get_u8()? {
+            CREATE_STUDENT_TAG => {
+                RecordData::CreateStudent(reader.get_str()?, reader.get_i32()?)
+            }
+            UPDATE_QUALIFICATION_TAG => {
+                RecordData::UpdateQualification(reader.get_str()?, reader.get_i32()?)
+            }
+            REMOVE_STUDENT_TAG => RecordData::RemoveStudent(reader.get_str()?),
+            ADD_SUBJECT_GRADE_TAG => RecordData::AddSubjectGrade(
+                reader.get_str()?,
+                reader.get_str()?,
+                reader.get_i32()?,
+            ),
+            TRANSFER_STUDENT_TAG => {
+                RecordData::TransferStudent(reader.get_str()?, reader.get_str()?)
+            }
+            ADD_ADMIN_TAG => RecordData::AddAdmin(read_public_key(reader)?),
+            GRANT_PERMISSION_TAG => {
+                RecordData::GrantPermission(read_public_key(reader)?, decode_opt_str(reader)?)
+            }
+            REVOKE_PERMISSION_TAG => {
+                RecordData::RevokePermission(read_public_key(reader)?, decode_opt_str(reader)?)
+            }
+            GENESIS_TAG => RecordData::Genesis(GenesisConfig::decode(reader)?),
+            tag => return Err(format!("Unknown record type {}", tag)),
+        };
+        let signature = match reader.get_u8()? {
+            0 => None,
+            1 => Some(RecordSignature::decode(reader)?),
+            flag => return Err(format!("Invalid option flag {}", flag)),
         };
+        Ok(Record {
+            from,
+            created_at: Duration::new(secs, nanos),
+            record,
+            signature,
+        })
+    }
+}
+
+fn encode_opt_str(writer: &mut ByteWriter, value: &Option<String>) {
+    match value {
+        Some(value) => {
+            writer.put_u8(1);
+            writer.put_str(value);
+        }
+        None => writer.put_u8(0),
+    }
+}
+
+fn decode_opt_str(reader: &mut ByteReader) -> Result<Option<String>, String> {
+    match reader.get_u8()? {
+        0 => Ok(None),
+        1 => Ok(Some(reader.get_str()?)),
+        flag => Err(format!("Invalid option flag {}", flag)),
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::student_registry::StudentRegistry;
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Canonical bytes and hash of every kind of record.
+    /// If this test fails the hashes of every stored blockchain changed:
+    /// bump `BLOCK_FORMAT_VERSION` and update the vectors
+    #[test]
+    fn record_hashes_are_stable() {
+        let key = SigningKey::from_bytes(&[7; 32]).verifying_key();
+        let golden_vectors = vec![
+            (
+                RecordData::CreateStudent("100000".into(), 7),
+                "0000000e3132372e302e302e313a38303030000000005f5e10000754d4c00100000006313030303030\
+                 0000000700",
+                "f46e0671e65ef0d5f67b44dc62976c1b9096df79654f76caf3975831741aa077\
+                 cb97b48ca5d8df438acd4ed5f572fb46f137c813ab5a176a7ff801a9dae4c9d3",
+            ),
+            (
+                RecordData::UpdateQualification("100000".into(), 9),
+                "0000000e3132372e302e302e313a38303030000000005f5e10000754d4c00200000006313030303030\
+                 0000000900",
+                "be1927b0737cfd7607b09f0ed9766b2769049252d34c5548941574348afb13f4\
+                 6f332f4f3ff75b0b2e19cc950fe58f59d21b78e7d098d286f24b59955faa2072",
+            ),
+            (
+                RecordData::RemoveStudent("100000".into()),
+                "0000000e3132372e302e302e313a38303030000000005f5e10000754d4c0030000000631303030303000",
+                "c01d6206d3db40efc2c5bfc7eaf2cc93d59f2011acef1afa0c01f365ab4ebbc4\
+                 86a9e25346a31328a1a5ad5607070a5a3996fb4480a4cde2e4e6c5afa5264bb1",
+            ),
+            (
+                RecordData::AddSubjectGrade("100000".into(), "Concurrentes".into(), 10),
+                "0000000e3132372e302e302e313a38303030000000005f5e10000754d4c00400000006313030303030\
+                 0000000c436f6e63757272656e7465730000000a00",
+                "65d15c280fd59e8a4b28d6cad3fae47ef5f333771b9fac00bc845d548456a16b\
+                 19c6bc664e1b4f0b428182bc646816b0e94cb5522ed60c258bd0959a49d38ca2",
+            ),
+            (
+                RecordData::TransferStudent("100000".into(), "100001".into()),
+                "0000000e3132372e302e302e313a38303030000000005f5e10000754d4c00500000006313030303030\
+                 0000000631303030303100",
+                "87b3bd92358648991215e62ef97f234836919f2dcbdb5d01ea565c784bc40633\
+                 6eb4fd3391726a1fab39bd9e4bdf650d65a3035a94c6857d8b44d943247ad84d",
+            ),
+            (
+                RecordData::AddAdmin(key),
+                "0000000e3132372e302e302e313a38303030000000005f5e10000754d4c0\
+                 06ea4a6c63e29c520abef5507b132ec5f9954776aebebe7b92421eea691446d22c00",
+                "78c5c435ecea627b2aec2e8803e4c35eebfee71901f0cd81734323671efdddef\
+                 32ee844c477ed9eea61201acee6ce58daeca546b341689ef5e7450ad7ce9b92a",
+            ),
+            (
+                RecordData::GrantPermission(key, Some("Concurrentes".into())),
+                "0000000e3132372e302e302e313a38303030000000005f5e10000754d4c0\
+                 07ea4a6c63e29c520abef5507b132ec5f9954776aebebe7b92421eea691446d22c\
+                 010000000c436f6e63757272656e74657300",
+                "acc3c9ef1918bd2119ba7373d6327f84ba6627d9bc016630d54faa44285ceae1\
+                 c8657778e702316465640103fa9cc2d878d240b2de9eb5634a59d812d8aed2e3",
+            ),
+            (
+                RecordData::RevokePermission(key, None),
+                "0000000e3132372e302e302e313a38303030000000005f5e10000754d4c0\
+                 08ea4a6c63e29c520abef5507b132ec5f9954776aebebe7b92421eea691446d22c0000",
+                "37dd842d0a46ee19a4b0cab968db0892d97c561d3f2d1658eb82b91541c350ad\
+                 16a8f8f4af9c3a166b576660a6705eedef2af678ffd2a86ebfa4c276dcd07be4",
+            ),
+            (
+                RecordData::Genesis(GenesisConfig {
+                    chain_id: "fiuba".into(),
+                    min_qualification: 4,
+                    max_qualification: 10,
+                    admins: vec![key],
+                }),
+                "0000000e3132372e302e302e313a38303030000000005f5e10000754d4c0\
+                 090000000566697562610000000400\
+                 00000a00000001ea4a6c63e29c520abef5507b132ec5f9954776aebebe7b92421eea691446d22c00",
+                "bbd55f5a6529e3e6a0d811f727e1b4d5cd1a136d2f9841a7e26027e98372510f\
+                 610cdc9cfbfb34bc92eb0ededde7faad38c76a74703a87a3d857906cc3a49bc9",
+            ),
+        ];
+
+        for (data, bytes, hash) in golden_vectors {
+            let record = Record::new(
+                "127.0.0.1:8000".into(),
+                data,
+                Duration::new(1_600_000_000, 123_000_000),
+            );
+            assert_eq!(to_hex(&record.canonical_bytes()), bytes);
+            assert_eq!(record.calculate_hash().to_string(), hash);
+        }
+    }
+
+    #[test]
+    fn only_records_signed_by_their_key_are_valid() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut record = Record::new(
+            "127.0.0.1:8000".into(),
+            RecordData::CreateStudent("100000".into(), 7),
+            Duration::new(1_600_000_000, 123_000_000),
+        );
+        assert_eq!(record.verify_signature(), Err(RecordError::MissingSignature));
+
+        record.sign(&key);
+        assert_eq!(record.verify_signature(), Ok(&key.verifying_key()));
+
+        let bytes = record.canonical_bytes();
+        let decoded = Record::decode(&mut ByteReader::new(&bytes)).unwrap();
+        assert_eq!(decoded.verify_signature(), Ok(&key.verifying_key()));
+
+        let mut forged = record.clone();
+        forged.record = RecordData::CreateStudent("100000".into(), 10);
+        assert_eq!(forged.verify_signature(), Err(RecordError::InvalidSignature));
+
+        let mut other_signer = record;
+        other_signer.signature.as_mut().unwrap().public_key =
+            SigningKey::from_bytes(&[8; 32]).verifying_key();
+        assert_eq!(other_signer.verify_signature(), Err(RecordError::InvalidSignature));
+    }
+
+    #[test]
+    fn records_need_the_permission_of_their_signer() {
+        let admin = SigningKey::from_bytes(&[1; 32]);
+        let professor = SigningKey::from_bytes(&[2; 32]);
+        let signed = |key: &SigningKey, data: RecordData| {
+            let mut record = Record::new("127.0.0.1:8000".into(), data, Duration::from_secs(0));
+            record.sign(key);
+            record
+        };
+        let mut state = StudentRegistry::default();
+        let create = RecordData::CreateStudent("100000".into(), 7);
+        let grade = RecordData::AddSubjectGrade("100000".into(), "Concurrentes".into(), 10);
+
+        assert_eq!(
+            signed(&admin, create.clone()).execute(&mut state),
+            Err(RecordError::Unauthorized(None))
+        );
+        // Sin admins nadie puede agregar uno, el primero viene del bloque genesis
+        let add_admin = RecordData::AddAdmin(admin.verifying_key());
+        assert_eq!(
+            signed(&admin, add_admin).execute(&mut state),
+            Err(RecordError::NotAnAdmin)
+        );
+        let genesis = RecordData::Genesis(GenesisConfig::new("test".into(), admin.verifying_key()));
+        assert_eq!(signed(&admin, genesis).execute(&mut state), Ok(()));
+        let add_admin = RecordData::AddAdmin(professor.verifying_key());
+        assert_eq!(
+            signed(&professor, add_admin).execute(&mut state),
+            Err(RecordError::NotAnAdmin)
+        );
 
-    /// Calculates the hash using Blake2 hasher
-    pub fn calculate_hash(&self) -> Vec<u8> {
-        let mut hasher = Blake2b::new();
-        let record_as_string = format!("{:?}", (&self.created_at, &self.record, &self.from));
+        assert_eq!(signed(&admin, create).execute(&mut state), Ok(()));
+        let grant = RecordData::GrantPermission(professor.verifying_key(), Some("Algebra".into()));
+        assert_eq!(signed(&admin, grant).execute(&mut state), Ok(()));
+        assert_eq!(
+            signed(&professor, grade.clone()).execute(&mut state),
+            Err(RecordError::Unauthorized(Some("Concurrentes".into())))
+        );
 
-        hasher.update(&record_as_string);
-        return Vec::from(hasher.finalize().as_ref());
+        let subject = Some("Concurrentes".into());
+        let grant = RecordData::GrantPermission(professor.verifying_key(), subject);
+        assert_eq!(signed(&admin, grant).execute(&mut state), Ok(()));
+        assert_eq!(signed(&professor, grade).execute(&mut state), Ok(()));
     }
 }
@@ -0,0 +1,128 @@
+use crate::encoder::{ByteReader, ByteWriter};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use ed25519_dalek::{PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH, SIGNATURE_LENGTH};
+use rand::rngs::OsRng;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fs::{self, OpenOptions};
+use std::io::{Error, ErrorKind, Write};
+
+/// Public key and signature of the node that submitted a record
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordSignature {
+    /// Key of the submitter, the signature is verified with it
+    pub public_key: VerifyingKey,
+
+    /// Ed25519 signature of the signed bytes of the record
+    pub signature: Signature,
+}
+
+impl RecordSignature {
+    /// Signs `message` with the key of the node
+    pub fn sign(key: &SigningKey, message: &[u8]) -> Self {
+        RecordSignature {
+            public_key: key.verifying_key(),
+            signature: key.sign(message),
+        }
+    }
+
+    /// Checks that the signature of `message` was made with the private part of `public_key`
+    pub fn verify(&self, message: &[u8]) -> bool {
+        self.public_key.verify(message, &self.signature).is_ok()
+    }
+
+    /// Writes `[public key: 32 bytes][signature: 64 bytes]`
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.put_raw(self.public_key.as_bytes());
+        writer.put_raw(&self.signature.to_bytes());
+    }
+
+    /// Reads a signature written by `RecordSignature::encode`
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        let mut public_key = [0; PUBLIC_KEY_LENGTH];
+        public_key.copy_from_slice(reader.get_raw(PUBLIC_KEY_LENGTH)?);
+        let public_key = decode_public_key(&public_key)?;
+
+        let mut signature = [0; SIGNATURE_LENGTH];
+        signature.copy_from_slice(reader.get_raw(SIGNATURE_LENGTH)?);
+        Ok(RecordSignature {
+            public_key,
+            signature: Signature::from_bytes(&signature),
+        })
+    }
+}
+
+/// Short hex representation of a public key, to show who signed a record
+pub struct KeyFingerprint<'a>(pub &'a VerifyingKey);
+
+impl Display for KeyFingerprint<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for byte in self.0.as_bytes().iter().take(8) {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Full hex representation of a public key, it can be read back with `parse_public_key`
+pub fn public_key_to_hex(key: &VerifyingKey) -> String {
+    key.as_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parses a public key written in hex
+pub fn parse_public_key(hex: &str) -> Result<VerifyingKey, String> {
+    if hex.len() != PUBLIC_KEY_LENGTH * 2 || !hex.is_ascii() {
+        return Err(format!("A public key must have {} hex digits", PUBLIC_KEY_LENGTH * 2));
+    }
+    let mut bytes = [0; PUBLIC_KEY_LENGTH];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("Invalid hex digits at position {}", i * 2))?;
+    }
+    decode_public_key(&bytes)
+}
+
+/// Reads a public key written as 32 raw bytes
+pub fn decode_public_key(bytes: &[u8; PUBLIC_KEY_LENGTH]) -> Result<VerifyingKey, String> {
+    VerifyingKey::from_bytes(bytes).map_err(|e| format!("Invalid public key: {}", e))
+}
+
+/// Reads the next 32 bytes of `reader` as a public key
+pub fn read_public_key(reader: &mut ByteReader) -> Result<VerifyingKey, String> {
+    let mut bytes = [0; PUBLIC_KEY_LENGTH];
+    bytes.copy_from_slice(reader.get_raw(PUBLIC_KEY_LENGTH)?);
+    decode_public_key(&bytes)
+}
+
+/// Reads the private key of the node from `filename`.
+/// The first time the node starts the file doesn't exist, so a new
+/// key is generated and saved there to be used on the next starts
+pub fn load_or_generate_key(filename: &str) -> Result<SigningKey, Error> {
+    match fs::read(filename) {
+        Ok(bytes) => {
+            if bytes.len() != SECRET_KEY_LENGTH {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("the key must have {} bytes", SECRET_KEY_LENGTH),
+                ));
+            }
+            let mut secret = [0; SECRET_KEY_LENGTH];
+            secret.copy_from_slice(&bytes);
+            Ok(SigningKey::from_bytes(&secret))
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            let key = SigningKey::generate(&mut OsRng);
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            // Solo el dueño puede leer la clave privada
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut file = options.open(filename)?;
+            file.write_all(key.as_bytes())?;
+            file.sync_data()?;
+            Ok(key)
+        }
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,332 @@
+use super::block::{BlockHeader, BLOCK_FORMAT_VERSION};
+use super::blockchain::Blockchain;
+use super::error::BlockchainError;
+use super::hash::{hash_bytes, BlockHash, HASH_SIZE};
+use super::state_machine::StateMachine;
+use crate::encoder::{ByteReader, ByteWriter};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// A snapshot is taken every time the height of the last block is a multiple of this
+pub const SNAPSHOT_INTERVAL: u64 = 10;
+
+/// Identifies the file as a snapshot
+const SNAPSHOT_MAGIC: &[u8; 4] = b"BCSN";
+
+/// World state after some block, tagged with the header of that block.
+/// A node can start from a snapshot instead of replaying every block
+/// since the genesis block: the state is trusted because its hash
+/// is the state root committed in the header
+#[derive(Clone, Debug)]
+pub struct StateSnapshot<S: StateMachine> {
+    /// Hash of the first block of the blockchain, identifies the network
+    pub genesis_hash: BlockHash,
+
+    /// Header of the last block executed to get the state
+    pub header: BlockHeader,
+
+    /// The world state after that block
+    pub state: S,
+}
+
+impl<S: StateMachine> StateSnapshot<S> {
+    /// Height of the last block executed to get the state
+    pub fn height(&self) -> u64 {
+        self.header.height
+    }
+
+    /// Hash of the last block executed to get the state
+    pub fn block_hash(&self) -> BlockHash {
+        self.header.hash()
+    }
+
+    /// Checks that the state is the one committed in the header of the block,
+    /// that the header was mined with `difficulty` and that the snapshot
+    /// belongs to the network of `genesis_hash` (if the node already knows it).
+    /// Without the proof of work anyone could build a header for any state
+    pub fn verify(&self, difficulty: u32, genesis_hash: Option<BlockHash>) -> Result<(), BlockchainError> {
+        if self.header.version != BLOCK_FORMAT_VERSION {
+            return Err(BlockchainError::UnsupportedVersion(self.header.version));
+        }
+        if self.header.difficulty != difficulty {
+            return Err(BlockchainError::InvalidDifficulty(difficulty, self.header.difficulty));
+        }
+        if self.block_hash().leading_zero_bits() < difficulty {
+            return Err(BlockchainError::InsufficientWork);
+        }
+        if genesis_hash.is_some_and(|genesis_hash| genesis_hash != self.genesis_hash) {
+            return Err(BlockchainError::InvalidGenesis);
+        }
+        if self.height() == 0 && self.genesis_hash != self.block_hash() {
+            return Err(BlockchainError::InvalidGenesis);
+        }
+        if self.state.state_root() != self.header.state_root {
+            return Err(BlockchainError::InvalidStateRoot);
+        }
+        Ok(())
+    }
+
+    /// Writes `[genesis hash][block header][state]`
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.put_raw(self.genesis_hash.as_bytes());
+        self.header.encode(writer);
+        self.state.encode(writer);
+    }
+
+    /// Reads a snapshot written by `StateSnapshot::encode`
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        Ok(StateSnapshot {
+            genesis_hash: BlockHash::from_slice(reader.get_raw(HASH_SIZE)?)?,
+            header: BlockHeader::decode(reader)?,
+            state: S::decode(reader)?,
+        })
+    }
+
+    /// Serializes the snapshot to be sent to another node
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = ByteWriter::new();
+        self.encode(&mut writer);
+        writer.into_bytes()
+    }
+
+    /// Reads a snapshot received from another node, it must use every byte
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let mut reader = ByteReader::new(data);
+        let snapshot = StateSnapshot::decode(&mut reader)?;
+        if !reader.is_empty() {
+            return Err("Unexpected bytes at the end of the snapshot".to_string());
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Parts of a serialized snapshot sent by the leader. They travel in separate
+/// datagrams that may arrive in any order or get lost, so each one has its index
+#[derive(Debug, Default)]
+pub struct SnapshotChunks {
+    total: Option<u32>,
+    chunks: BTreeMap<u32, Vec<u8>>,
+}
+
+impl SnapshotChunks {
+    /// Keeps the part `index` of a snapshot of `total` parts
+    pub fn add(&mut self, index: u32, total: u32, chunk: Vec<u8>) -> Result<(), String> {
+        if *self.total.get_or_insert(total) != total {
+            return Err(format!("The part {} belongs to a snapshot of {} parts", index, total));
+        }
+        if index >= total {
+            return Err(format!("Invalid part {} of a snapshot of {} parts", index, total));
+        }
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total.is_none()
+    }
+
+    /// Joins the parts in order, fails if any of them is missing
+    pub fn assemble(self) -> Result<Vec<u8>, String> {
+        let total = self.total.unwrap_or(0);
+        if self.chunks.len() as u32 != total {
+            return Err(format!(
+                "The snapshot is incomplete, {} of {} parts arrived",
+                self.chunks.len(),
+                total
+            ));
+        }
+        Ok(self.chunks.into_values().flatten().collect())
+    }
+}
+
+/// File with the last snapshot taken by the node.
+/// The file is `[magic][block format version: u32][checksum][snapshot]`,
+/// it's written in a temporary file that then replaces the old one,
+/// so a crash while writing keeps the previous snapshot
+#[derive(Debug)]
+pub struct SnapshotStore {
+    filename: String,
+
+    // Evita que dos hilos escriban el archivo temporal a la vez
+    lock: Mutex<()>,
+}
+
+impl SnapshotStore {
+    pub fn new(filename: &str) -> Self {
+        SnapshotStore {
+            filename: filename.to_string(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Reads the saved snapshot, None if the node never saved one
+    pub fn load<S: StateMachine>(&self) -> Result<Option<StateSnapshot<S>>, Error> {
+        let _guard = self.lock.lock().unwrap();
+        if !Path::new(&self.filename).exists() {
+            return Ok(None);
+        }
+        let data = fs::read(&self.filename)?;
+        let invalid = |reason: String| Error::new(ErrorKind::InvalidData, reason);
+
+        let header = store_header();
+        if data.len() < header.len() + HASH_SIZE || data[..header.len()] != header[..] {
+            return Err(invalid(format!(
+                "not a snapshot of version {}",
+                BLOCK_FORMAT_VERSION
+            )));
+        }
+        let payload = &data[header.len() + HASH_SIZE..];
+        if hash_bytes(payload).as_bytes()[..] != data[header.len()..header.len() + HASH_SIZE] {
+            return Err(invalid("the checksum of the snapshot doesn't match".to_string()));
+        }
+        StateSnapshot::from_bytes(payload).map(Some).map_err(invalid)
+    }
+
+    /// Replaces the saved snapshot and waits until it reaches the disk
+    pub fn save<S: StateMachine>(&self, snapshot: &StateSnapshot<S>) -> Result<(), Error> {
+        let payload = snapshot.to_bytes();
+        let mut data = store_header();
+        data.extend_from_slice(hash_bytes(&payload).as_bytes());
+        data.extend(payload);
+
+        let _guard = self.lock.lock().unwrap();
+        let tmp_filename = format!("{}.tmp", self.filename);
+        let mut file = File::create(&tmp_filename)?;
+        file.write_all(&data)?;
+        file.sync_data()?;
+        fs::rename(tmp_filename, &self.filename)
+    }
+
+    /// Saves a snapshot of the blockchain if the height of its last block
+    /// is a multiple of `SNAPSHOT_INTERVAL`. Returns the height of the saved snapshot
+    pub fn save_if_due<S: StateMachine>(&self, blockchain: &Blockchain<S>) -> Result<Option<u64>, Error> {
+        let height = match blockchain.len() {
+            0 => return Ok(None),
+            len => len as u64 - 1,
+        };
+        if height == 0 || height % SNAPSHOT_INTERVAL != 0 {
+            return Ok(None);
+        }
+        match blockchain.take_snapshot() {
+            Some(snapshot) => self.save(&snapshot).map(|_| Some(height)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn store_header() -> Vec<u8> {
+    let mut header = SNAPSHOT_MAGIC.to_vec();
+    header.extend_from_slice(&BLOCK_FORMAT_VERSION.to_be_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::genesis::GenesisConfig;
+    use crate::blockchain::record::{Record, RecordData};
+    use crate::blockchain::mining::mine;
+    use crate::blockchain::student::Student;
+    use crate::blockchain::student_registry::StudentRegistry;
+    use ed25519_dalek::SigningKey;
+    use std::time::Duration;
+
+    #[test]
+    fn nodes_sync_from_a_snapshot_and_the_blocks_after_it() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let proposer = "127.0.0.1:8000".to_string();
+        let mut leader = Blockchain::<StudentRegistry>::with_difficulty(0);
+        let config = GenesisConfig::new("test".into(), key.verifying_key());
+        let genesis = leader.seal_genesis(config, proposer.clone(), Duration::from_secs(1), &key);
+        leader.append_block(genesis).unwrap();
+        for (i, id) in ["100000", "100001", "100002"].iter().enumerate() {
+            let created_at = Duration::from_secs(2 + i as u64);
+            let data = RecordData::CreateStudent(id.to_string(), 7);
+            let mut record = Record::new(proposer.clone(), data, created_at);
+            record.sign(&key);
+            leader.submit_record(record).unwrap();
+            let block = leader.seal_block(10, proposer.clone(), created_at).unwrap();
+            leader.append_block(block).unwrap();
+        }
+
+        // Snapshot despues del bloque 2, el bloque 3 se envia aparte
+        let old = Blockchain::<StudentRegistry>::from_blocks(leader.blocks[..3].to_vec(), 0).unwrap();
+        let snapshot = old.take_snapshot().unwrap();
+        let received = StateSnapshot::<StudentRegistry>::from_bytes(&snapshot.to_bytes()).unwrap();
+        let blocks = leader.get_blocks_after(&received).unwrap();
+        assert_eq!(blocks.len(), 1);
+
+        let synced = Blockchain::from_snapshot(received, blocks, 0, leader.get_genesis_hash()).unwrap();
+        assert_eq!(synced.len(), leader.len());
+        assert_eq!(synced.first_height(), 3);
+        assert_eq!(synced.get_genesis_hash(), leader.get_genesis_hash());
+        assert_eq!(synced.get_last_block_hash(), leader.get_last_block_hash());
+        assert_eq!(synced.state.state_root(), leader.state.state_root());
+        assert!(synced.validate().is_ok());
+
+        let mut tampered = snapshot;
+        tampered.state.students.get_mut("100000").unwrap().qualification = 10;
+        assert_eq!(
+            Blockchain::from_snapshot(tampered, Vec::new(), 0, None).unwrap_err().reason,
+            BlockchainError::InvalidStateRoot
+        );
+    }
+
+    #[test]
+    fn a_forged_snapshot_is_rejected() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let proposer = "127.0.0.1:8000".to_string();
+        let mut blockchain = Blockchain::<StudentRegistry>::with_difficulty(8);
+        let config = GenesisConfig::new("test".into(), key.verifying_key());
+        let mut genesis = blockchain.seal_genesis(config, proposer, Duration::from_secs(1), &key);
+        mine(&mut genesis, || false).unwrap();
+        blockchain.append_block(genesis).unwrap();
+        let snapshot = blockchain.take_snapshot().unwrap();
+        let genesis_hash = blockchain.get_genesis_hash();
+        assert_eq!(snapshot.verify(8, genesis_hash), Ok(()));
+
+        // Un header con otro state root no tiene la prueba de trabajo
+        let mut forged = snapshot.clone();
+        forged.state.students.insert("100000".into(), Student::new(10));
+        forged.header.state_root = forged.state.state_root();
+        assert_eq!(forged.verify(8, None), Err(BlockchainError::InsufficientWork));
+        assert_eq!(snapshot.verify(0, None), Err(BlockchainError::InvalidDifficulty(0, 8)));
+
+        // Ni puede ser de otra red, aunque no sea del bloque genesis
+        let mut other_network = snapshot;
+        other_network.header.height = 5;
+        mine_header(&mut other_network.header, 8);
+        assert_eq!(other_network.verify(8, None), Ok(()));
+        assert_eq!(other_network.verify(8, Some(hash_bytes(b"other"))), Err(BlockchainError::InvalidGenesis));
+    }
+
+    /// Finds a nonce for the header without a block, like a peer forging it would
+    fn mine_header(header: &mut BlockHeader, difficulty: u32) {
+        header.nonce = 0;
+        while header.hash().leading_zero_bits() < difficulty {
+            header.nonce += 1;
+        }
+    }
+
+    #[test]
+    fn snapshot_chunks_are_joined_by_index_and_must_be_complete() {
+        let mut chunks = SnapshotChunks::default();
+        chunks.add(2, 3, vec![5]).unwrap();
+        chunks.add(0, 3, vec![1, 2]).unwrap();
+        chunks.add(1, 3, vec![3, 4]).unwrap();
+        assert_eq!(chunks.assemble().unwrap(), vec![1, 2, 3, 4, 5]);
+
+        let mut incomplete = SnapshotChunks::default();
+        incomplete.add(0, 3, vec![1, 2]).unwrap();
+        incomplete.add(2, 3, vec![5]).unwrap();
+        assert!(incomplete.assemble().is_err());
+
+        let mut inconsistent = SnapshotChunks::default();
+        inconsistent.add(0, 2, vec![1]).unwrap();
+        assert!(inconsistent.add(1, 3, vec![2]).is_err());
+        assert!(inconsistent.add(2, 2, vec![2]).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use super::error::RecordError;
+use super::hash::{hash_bytes, BlockHash};
+use super::record::Record;
+use crate::encoder::{ByteReader, ByteWriter};
+
+/// Application whose state is replicated by the blockchain.
+/// The blockchain orders, signs, hashes and stores the records and
+/// the state machine gives them meaning: every node applies the same
+/// records in the same order so every node ends in the same state
+pub trait StateMachine: Clone + Default {
+    /// Copy of the state used to undo records that were applied
+    type Snapshot;
+
+    /// Changes the state with the record, the state must not change if it fails
+    fn apply(&mut self, record: &Record) -> Result<(), RecordError>;
+
+    /// Saves the current state
+    fn snapshot(&self) -> Self::Snapshot;
+
+    /// Goes back to a saved state
+    fn restore(&mut self, snapshot: Self::Snapshot);
+
+    /// Serializes the whole state. Two equal states must always be written
+    /// with the same bytes (e.g. maps sorted by key), the state root is their hash
+    fn encode(&self, writer: &mut ByteWriter);
+
+    /// Reads a state written by `StateMachine::encode`
+    fn decode(reader: &mut ByteReader) -> Result<Self, String>;
+
+    /// Hash of the whole state, committed in the header of every block
+    fn state_root(&self) -> BlockHash {
+        let mut writer = ByteWriter::new();
+        self.encode(&mut writer);
+        hash_bytes(&writer.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::block::Block;
+    use crate::blockchain::blockchain::Blockchain;
+    use crate::blockchain::error::BlockchainError;
+    use crate::blockchain::genesis::GenesisConfig;
+    use crate::blockchain::record::RecordData;
+    use ed25519_dalek::SigningKey;
+    use std::time::Duration;
+
+    /// Another application: it only counts the signed records
+    #[derive(Clone, Debug, Default)]
+    struct RecordCounter {
+        count: usize,
+    }
+
+    impl StateMachine for RecordCounter {
+        type Snapshot = usize;
+
+        fn apply(&mut self, record: &Record) -> Result<(), RecordError> {
+            record.verify_signature()?;
+            self.count += 1;
+            Ok(())
+        }
+
+        fn snapshot(&self) -> usize {
+            self.count
+        }
+
+        fn restore(&mut self, snapshot: usize) {
+            self.count = snapshot;
+        }
+
+        fn encode(&self, writer: &mut ByteWriter) {
+            writer.put_u64(self.count as u64);
+        }
+
+        fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+            Ok(RecordCounter {
+                count: reader.get_u64()? as usize,
+            })
+        }
+    }
+
+    #[test]
+    fn the_blockchain_replicates_any_state_machine() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let proposer = "127.0.0.1:8000".to_string();
+        let record = |signed: bool| {
+            let data = RecordData::RemoveStudent("100000".into());
+            let mut record = Record::new(proposer.clone(), data, Duration::from_secs(2));
+            if signed {
+                record.sign(&key);
+            }
+            record
+        };
+
+        let mut blockchain = Blockchain::<RecordCounter>::with_difficulty(0);
+        let config = GenesisConfig::new("test".into(), key.verifying_key());
+        let genesis = blockchain.seal_genesis(config, proposer.clone(), Duration::from_secs(1), &key);
+        blockchain.append_block(genesis).unwrap();
+        assert_eq!(blockchain.state.count, 1);
+
+        // Los registros pendientes se prueban sin cambiar el estado
+        assert!(blockchain.submit_record(record(true)).is_ok());
+        assert_eq!(blockchain.submit_record(record(false)), Err(RecordError::MissingSignature));
+        assert_eq!(blockchain.state.count, 1);
+
+        let block = blockchain.seal_block(10, proposer.clone(), Duration::from_secs(2)).unwrap();
+        blockchain.append_block(block).unwrap();
+        assert_eq!(blockchain.state.count, 2);
+
+        // Un bloque con un registro invalido no cambia el estado
+        let mut block = Block::new(2, blockchain.get_last_block_hash(), proposer.clone(), Duration::from_secs(3));
+        block.add_record(record(true));
+        block.add_record(record(false));
+        assert_eq!(
+            blockchain.append_block(block),
+            Err(BlockchainError::InvalidRecord(2, RecordError::MissingSignature))
+        );
+        assert_eq!(blockchain.state.count, 2);
+
+        // Un bloque que no termina en su state root tampoco
+        let mut block = Block::new(2, blockchain.get_last_block_hash(), proposer.clone(), Duration::from_secs(3));
+        block.add_record(record(true));
+        assert_eq!(blockchain.append_block(block), Err(BlockchainError::InvalidStateRoot));
+        assert_eq!(blockchain.state.count, 2);
+        assert_eq!(blockchain.len(), 2);
+    }
+}
@@ -1,3 +1,6 @@
+use crate::encoder::{ByteReader, ByteWriter};
+use std::collections::BTreeMap;
+
 /// Represents a student on the blockchain
 /// Is the primary part of the "world state" of the blockchain
 /// (the final status after performing all blocks in order)
@@ -5,11 +8,38 @@
 pub struct Student {
     /// qualification
     pub qualification: i32,
+
+    /// Grade of every subject the student passed, by subject name
+    pub subjects: BTreeMap<String, i32>,
 }
 
 impl Student {
     /// Constructor
     pub fn new(qualification: i32) -> Self {
-        Student { qualification }
+        Student {
+            qualification,
+            subjects: BTreeMap::new(),
+        }
+    }
+
+    /// Writes `[qualification: i32][subject count: u32]` and every
+    /// `[subject][grade: i32]` sorted by subject
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.put_i32(self.qualification);
+        writer.put_u32(self.subjects.len() as u32);
+        for (subject, grade) in self.subjects.iter() {
+            writer.put_str(subject);
+            writer.put_i32(*grade);
+        }
+    }
+
+    /// Reads a student written by `Student::encode`
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        let mut student = Student::new(reader.get_i32()?);
+        for _ in 0..reader.get_u32()? {
+            let subject = reader.get_str()?;
+            student.subjects.insert(subject, reader.get_i32()?);
+        }
+        Ok(student)
     }
 }
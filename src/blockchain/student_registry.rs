@@ -0,0 +1,117 @@
+use super::error::RecordError;
+use super::genesis::GenesisConfig;
+use super::permissions::Permissions;
+use super::record::Record;
+use super::state_machine::StateMachine;
+use super::student::Student;
+use crate::encoder::{ByteReader, ByteWriter};
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// The world state of the student grades: every student with its grades,
+/// who can change them and the configuration of the network
+#[derive(Clone, Debug, Default)]
+pub struct StudentRegistry {
+    /// Every registered student by id
+    pub students: HashMap<String, Student>,
+
+    /// Who can submit records
+    pub permissions: Permissions,
+
+    /// Configuration of the network, set by the genesis block
+    pub genesis: Option<GenesisConfig>,
+}
+
+impl StateMachine for StudentRegistry {
+    type Snapshot = StudentRegistry;
+
+    fn apply(&mut self, record: &Record) -> Result<(), RecordError> {
+        record.execute(self)
+    }
+
+    fn snapshot(&self) -> Self::Snapshot {
+        self.clone()
+    }
+
+    fn restore(&mut self, snapshot: Self::Snapshot) {
+        *self = snapshot;
+    }
+
+    /// Writes `[genesis flag: u8][genesis][permissions][student count: u32]`
+    /// and every `[id][student]` sorted by id
+    fn encode(&self, writer: &mut ByteWriter) {
+        match &self.genesis {
+            Some(genesis) => {
+                writer.put_u8(1);
+                genesis.encode(writer);
+            }
+            None => writer.put_u8(0),
+        }
+        self.permissions.encode(writer);
+
+        let mut ids: Vec<&String> = self.students.keys().collect();
+        ids.sort();
+        writer.put_u32(ids.len() as u32);
+        for id in ids {
+            writer.put_str(id);
+            self.students[id].encode(writer);
+        }
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        let genesis = match reader.get_u8()? {
+            0 => None,
+            1 => Some(GenesisConfig::decode(reader)?),
+            flag => return Err(format!("Invalid option flag {}", flag)),
+        };
+        let permissions = Permissions::decode(reader)?;
+        let mut students = HashMap::new();
+        for _ in 0..reader.get_u32()? {
+            let id = reader.get_str()?;
+            students.insert(id, Student::decode(reader)?);
+        }
+        Ok(StudentRegistry {
+            students,
+            permissions,
+            genesis,
+        })
+    }
+}
+
+impl Display for StudentRegistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut data = "Blockchain:\n\tPadron\tNota\tMaterias".to_string();
+        for (padron, q) in &self.students {
+            let subjects: Vec<String> = q
+                .subjects
+                .iter()
+                .map(|(subject, grade)| format!("{}: {}", subject, grade))
+                .collect();
+            data = format!("{}\n\t{}\t{}\t{}", data, padron, q.qualification, subjects.join(", "));
+        }
+        write!(f, "{}", data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_states_have_the_same_state_root() {
+        let mut first = StudentRegistry::default();
+        let mut second = StudentRegistry::default();
+        for id in ["100000", "100001", "100002"].iter() {
+            first.students.insert(id.to_string(), Student::new(7));
+        }
+        for id in ["100002", "100000", "100001"].iter() {
+            second.students.insert(id.to_string(), Student::new(7));
+        }
+        assert_eq!(first.state_root(), second.state_root());
+
+        second.students.get_mut("100001").unwrap().subjects.insert("Concurrentes".into(), 10);
+        assert_ne!(first.state_root(), second.state_root());
+    }
+}
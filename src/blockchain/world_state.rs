@@ -1,5 +1,9 @@
-use super::blockchain::Blockchain;
+use super::error::RecordError;
+use super::genesis::{GenesisConfig, DEFAULT_MAX_QUALIFICATION, DEFAULT_MIN_QUALIFICATION};
 use super::student::Student;
+use super::student_registry::StudentRegistry;
+use ed25519_dalek::VerifyingKey;
+
 /// The current state of the blockchain after all Blocks are added
 /// Interface into the Blockchain
 pub trait WorldState {
@@ -12,11 +16,53 @@ pub trait WorldState {
     /// Returns a student given the id if it's available
     fn get_student_by_id(&self, id: &String) -> Option<&Student>;
 
-    /// Adds a new student
-    fn create_student(&mut self, id: String, qualification: i32) -> Result<(), &'static str>;
+    /// Adds a new student, fails if the id is already registered
+    fn create_student(&mut self, id: String, qualification: i32) -> Result<(), RecordError>;
+
+    /// Changes the qualification of an existing student
+    fn update_qualification(&mut self, id: &str, qualification: i32) -> Result<(), RecordError>;
+
+    /// Removes an existing student
+    fn remove_student(&mut self, id: &str) -> Result<(), RecordError>;
+
+    /// Adds the grade of a subject not graded yet to an existing student
+    fn add_subject_grade(&mut self, id: &str, subject: String, grade: i32) -> Result<(), RecordError>;
+
+    /// Moves an existing student to a new id that is not registered
+    fn transfer_student(&mut self, id: &str, new_id: String) -> Result<(), RecordError>;
+
+    /// Checks if `signer` can write grades of `subject` (or change students if None)
+    fn check_permission(&self, signer: &VerifyingKey, subject: Option<&str>) -> Result<(), RecordError>;
+
+    /// Adds an admin, `signer` has to be an admin (the first ones come from the genesis block)
+    fn add_admin(&mut self, signer: &VerifyingKey, key: VerifyingKey) -> Result<(), RecordError>;
+
+    /// Grants a permission to `key`, `signer` has to be an admin
+    fn grant_permission(&mut self, signer: &VerifyingKey, key: VerifyingKey, subject: Option<String>) -> Result<(), RecordError>;
+
+    /// Revokes a permission of `key`, `signer` has to be an admin
+    fn revoke_permission(&mut self, signer: &VerifyingKey, key: &VerifyingKey, subject: Option<&str>) -> Result<(), RecordError>;
+
+    /// Sets the configuration of the network and its first admins, only once
+    fn create_chain(&mut self, config: GenesisConfig) -> Result<(), RecordError>;
+}
+
+impl StudentRegistry {
+    /// Checks the qualification with the range of the genesis block (or the default range)
+    fn check_qualification(&self, qualification: i32) -> Result<(), RecordError> {
+        let (min, max) = match &self.genesis {
+            Some(config) => (config.min_qualification, config.max_qualification),
+            None => (DEFAULT_MIN_QUALIFICATION, DEFAULT_MAX_QUALIFICATION),
+        };
+        if (min..=max).contains(&qualification) {
+            Ok(())
+        } else {
+            Err(RecordError::InvalidQualification(qualification, min, max))
+        }
+    }
 }
 
-impl WorldState for Blockchain {
+impl WorldState for StudentRegistry {
     fn get_student_ids(&self) -> Vec<String> {
         self.students.keys().map(|s| s.clone()).collect()
     }
@@ -29,13 +75,181 @@ impl WorldState for Blockchain {
         self.students.get(id)
     }
 
-    fn create_student(&mut self, id: String, qualification: i32) -> Result<(), &'static str> {
-        if qualification >= 1 && qualification <= 10 {
-            let acc = Student::new(qualification);
-            self.students.insert(id, acc);
-            return Ok(());
+    fn create_student(&mut self, id: String, qualification: i32) -> Result<(), RecordError> {
+        self.check_qualification(qualification)?;
+        if self.students.contains_key(&id) {
+            return Err(RecordError::DuplicateStudent(id));
+        }
+        self.students.insert(id, Student::new(qualification));
+        Ok(())
+    }
+
+    fn update_qualification(&mut self, id: &str, qualification: i32) -> Result<(), RecordError> {
+        self.check_qualification(qualification)?;
+        match self.students.get_mut(id) {
+            Some(student) => {
+                student.qualification = qualification;
+                Ok(())
+            }
+            None => Err(RecordError::UnknownStudent(id.to_string())),
+        }
+    }
+
+    fn remove_student(&mut self, id: &str) -> Result<(), RecordError> {
+        match self.students.remove(id) {
+            Some(_) => Ok(()),
+            None => Err(RecordError::UnknownStudent(id.to_string())),
+        }
+    }
+
+    fn add_subject_grade(&mut self, id: &str, subject: String, grade: i32) -> Result<(), RecordError> {
+        self.check_qualification(grade)?;
+        let student = match self.students.get_mut(id) {
+            Some(student) => student,
+            None => return Err(RecordError::UnknownStudent(id.to_string())),
+        };
+        if student.subjects.contains_key(&subject) {
+            return Err(RecordError::SubjectAlreadyGraded(id.to_string(), subject));
+        }
+        student.subjects.insert(subject, grade);
+        Ok(())
+    }
+
+    fn transfer_student(&mut self, id: &str, new_id: String) -> Result<(), RecordError> {
+        if self.students.contains_key(&new_id) {
+            return Err(RecordError::DuplicateStudent(new_id));
+        }
+        match self.students.remove(id) {
+            Some(student) => {
+                self.students.insert(new_id, student);
+                Ok(())
+            }
+            None => Err(RecordError::UnknownStudent(id.to_string())),
+        }
+    }
+
+    fn check_permission(&self, signer: &VerifyingKey, subject: Option<&str>) -> Result<(), RecordError> {
+        if self.permissions.allows(signer, subject) {
+            Ok(())
         } else {
-            return Err("Qualification must be between 1 and 10");
+            Err(RecordError::Unauthorized(subject.map(|s| s.to_string())))
+        }
+    }
+
+    fn add_admin(&mut self, signer: &VerifyingKey, key: VerifyingKey) -> Result<(), RecordError> {
+        // Los primeros admins los pone el bloque genesis, si no el primero que
+        // envie un registro se podria hacer admin
+        if !self.permissions.is_admin(signer) {
+            return Err(RecordError::NotAnAdmin);
+        }
+        self.permissions.add_admin(key);
+        Ok(())
+    }
+
+    fn grant_permission(&mut self, signer: &VerifyingKey, key: VerifyingKey, subject: Option<String>) -> Result<(), RecordError> {
+        if !self.permissions.is_admin(signer) {
+            return Err(RecordError::NotAnAdmin);
+        }
+        self.permissions.grant(key, subject);
+        Ok(())
+    }
+
+    fn revoke_permission(&mut self, signer: &VerifyingKey, key: &VerifyingKey, subject: Option<&str>) -> Result<(), RecordError> {
+        if !self.permissions.is_admin(signer) {
+            return Err(RecordError::NotAnAdmin);
+        }
+        if self.permissions.revoke(key, subject) {
+            Ok(())
+        } else {
+            Err(RecordError::PermissionNotFound)
+        }
+    }
+
+    fn create_chain(&mut self, config: GenesisConfig) -> Result<(), RecordError> {
+        if self.genesis.is_some() {
+            return Err(RecordError::ChainAlreadyCreated);
         }
+        for admin in config.admins.iter() {
+            self.permissions.add_admin(*admin);
+        }
+        self.genesis = Some(config);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    /// Registry with the students `100000` (graded in Concurrentes) and `100001`
+    fn registry() -> StudentRegistry {
+        let mut registry = StudentRegistry::default();
+        registry.create_student("100000".into(), 7).unwrap();
+        registry.create_student("100001".into(), 8).unwrap();
+        registry.add_subject_grade("100000", "Concurrentes".into(), 9).unwrap();
+        registry
+    }
+
+    #[test]
+    fn qualifications_out_of_the_range_are_rejected() {
+        let mut registry = registry();
+        let out_of_range = Err(RecordError::InvalidQualification(11, 1, 10));
+        assert_eq!(registry.create_student("100002".into(), 11), out_of_range);
+        assert_eq!(registry.update_qualification("100000", 11), out_of_range);
+        assert_eq!(registry.add_subject_grade("100001", "Algebra".into(), 11), out_of_range);
+        assert_eq!(
+            registry.create_student("100002".into(), 0),
+            Err(RecordError::InvalidQualification(0, 1, 10))
+        );
+
+        // El rango del bloque genesis reemplaza al de por defecto
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let mut config = GenesisConfig::new("test".into(), key.verifying_key());
+        config.min_qualification = 4;
+        config.max_qualification = 8;
+        registry.create_chain(config).unwrap();
+        assert_eq!(
+            registry.update_qualification("100000", 9),
+            Err(RecordError::InvalidQualification(9, 4, 8))
+        );
+        assert_eq!(registry.get_student_by_id(&"100000".to_string()).unwrap().qualification, 7);
+    }
+
+    #[test]
+    fn students_cant_be_created_twice() {
+        let mut registry = registry();
+        assert_eq!(
+            registry.create_student("100000".into(), 5),
+            Err(RecordError::DuplicateStudent("100000".into()))
+        );
+        assert_eq!(
+            registry.transfer_student("100000", "100001".into()),
+            Err(RecordError::DuplicateStudent("100001".into()))
+        );
+        assert_eq!(registry.get_student_by_id(&"100000".to_string()).unwrap().qualification, 7);
+        assert_eq!(registry.get_student_by_id(&"100001".to_string()).unwrap().qualification, 8);
+    }
+
+    #[test]
+    fn unknown_students_cant_be_changed() {
+        let mut registry = registry();
+        let unknown = Err(RecordError::UnknownStudent("100009".into()));
+        assert_eq!(registry.update_qualification("100009", 5), unknown);
+        assert_eq!(registry.remove_student("100009"), unknown);
+        assert_eq!(registry.add_subject_grade("100009", "Algebra".into(), 5), unknown);
+        assert_eq!(registry.transfer_student("100009", "100002".into()), unknown);
+        assert_eq!(registry.get_student_ids().len(), 2);
+    }
+
+    #[test]
+    fn subjects_are_graded_only_once() {
+        let mut registry = registry();
+        assert_eq!(
+            registry.add_subject_grade("100000", "Concurrentes".into(), 4),
+            Err(RecordError::SubjectAlreadyGraded("100000".into(), "Concurrentes".into()))
+        );
+        let student = registry.get_student_by_id(&"100000".to_string()).unwrap();
+        assert_eq!(student.subjects.get("Concurrentes"), Some(&9));
     }
 }
@@ -0,0 +1,284 @@
+use crate::blockchain::mining::MAX_DIFFICULTY;
+use std::fs;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+/// Cluster of the nodes that don't configure one
+pub const DEFAULT_CLUSTER_ID: &str = "blockchain";
+
+/// Ports of the local cluster used when the node is started only with a port
+const LOCAL_FIRST_PORT: u16 = 8000;
+const LOCAL_NODES: u16 = 50;
+
+/// Time between the heartbeats of the leader
+const DEFAULT_HEARTBEAT_INTERVAL_MILLIS: u64 = 500;
+
+/// Time without messages of the leader until it's suspected down
+const DEFAULT_HEARTBEAT_TIMEOUT_MILLIS: u64 = 2000;
+
+/// How the nodes agree on the order of the blocks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConsensusMode {
+    /// Bully election, the distributed mutex orders the records
+    /// and the leader broadcasts every block
+    Bully,
+    /// Raft: a block is appended only after a majority of the nodes stored it
+    Raft,
+}
+
+/// Where the node listens and which nodes form its cluster
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeConfig {
+    /// Address the socket is bound to, the other nodes send messages to it
+    pub bind: SocketAddr,
+
+    /// Address of every other node of the cluster
+    pub peers: Vec<SocketAddr>,
+
+    /// Name of the cluster, the messages of other clusters are ignored
+    pub cluster_id: String,
+
+    /// Leading zero bits the hash of every block must have (0 disables mining)
+    pub difficulty: u32,
+
+    /// Time between the heartbeats the leader sends to every node
+    pub heartbeat_interval: Duration,
+
+    /// Time without messages of the leader until a follower suspects it's down,
+    /// after twice this time a new leader is elected
+    pub heartbeat_timeout: Duration,
+
+    /// Consensus used by the cluster, every node must use the same one
+    pub consensus: ConsensusMode,
+}
+
+impl NodeConfig {
+    /// Reads the configuration from the arguments of the program, either
+    /// `<port> [difficulty]` to run in the local cluster (127.0.0.1:8000 to 8049) or
+    /// `[--config <file>] [--bind <host:port>] [--peers <host:port>,...]
+    /// [--cluster-id <id>] [--difficulty <n>] [--heartbeat-interval <ms>]
+    /// [--heartbeat-timeout <ms>] [--consensus bully|raft]` where the flags override the file
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        match args.first() {
+            None => Err("The port or the --bind flag must be specified".to_string()),
+            Some(first) if !first.starts_with("--") => NodeConfig::local(first, args.get(1)),
+            Some(_) => NodeConfig::from_flags(args),
+        }
+    }
+
+    /// Node of the local cluster listening in `port`
+    fn local(port: &str, difficulty: Option<&String>) -> Result<Self, String> {
+        let port = port
+            .parse::<u16>()
+            .map_err(|_| format!("Invalid port {}", port))?;
+        let bind = SocketAddr::from(([127, 0, 0, 1], port));
+        let peers = (LOCAL_FIRST_PORT..LOCAL_FIRST_PORT + LOCAL_NODES)
+            .map(|port| SocketAddr::from(([127, 0, 0, 1], port)))
+            .collect();
+        let entries = difficulty
+            .map(|difficulty| ("difficulty".to_string(), difficulty.clone()))
+            .into_iter()
+            .collect();
+        NodeConfig::build(Some(bind), peers, entries)
+    }
+
+    /// Reads the flags, and the file of `--config` if there is one. The file is like:
+    /// ```text
+    /// # comentario
+    /// bind = 10.0.0.1:9000
+    /// peers = 10.0.0.2:9000, 10.0.0.3:9000
+    /// cluster_id = fiuba
+    /// difficulty = 8
+    /// heartbeat_interval_ms = 500
+    /// heartbeat_timeout_ms = 2000
+    /// consensus = raft
+    /// ```
+    /// `bind` is required, the rest of the keys are optional
+    fn from_flags(args: &[String]) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        let mut flags = args.iter();
+        while let Some(flag) = flags.next() {
+            let value = flags
+                .next()
+                .ok_or_else(|| format!("Missing value of {}", flag))?;
+            match flag.as_str() {
+                "--config" => {
+                    let content = fs::read_to_string(value)
+                        .map_err(|e| format!("Unable to read {}: {}", value, e))?;
+                    // Las entradas del archivo van primero para que las pisen los flags
+                    let mut file_entries = parse_entries(&content)?;
+                    file_entries.append(&mut entries);
+                    entries = file_entries;
+                }
+                "--bind" => entries.push(("bind".to_string(), value.clone())),
+                "--peers" => entries.push(("peers".to_string(), value.clone())),
+                "--cluster-id" => entries.push(("cluster_id".to_string(), value.clone())),
+                "--difficulty" => entries.push(("difficulty".to_string(), value.clone())),
+                "--heartbeat-interval" => entries.push(("heartbeat_interval_ms".to_string(), value.clone())),
+                "--heartbeat-timeout" => entries.push(("heartbeat_timeout_ms".to_string(), value.clone())),
+                "--consensus" => entries.push(("consensus".to_string(), value.clone())),
+                _ => return Err(format!("Unknown flag {}", flag)),
+            }
+        }
+        NodeConfig::build(None, Vec::new(), entries)
+    }
+
+    /// Builds the configuration applying the `(key, value)` entries in order
+    fn build(
+        mut bind: Option<SocketAddr>,
+        mut peers: Vec<SocketAddr>,
+        entries: Vec<(String, String)>,
+    ) -> Result<Self, String> {
+        let mut cluster_id = DEFAULT_CLUSTER_ID.to_string();
+        let mut difficulty = 0;
+        let mut heartbeat_interval = Duration::from_millis(DEFAULT_HEARTBEAT_INTERVAL_MILLIS);
+        let mut heartbeat_timeout = Duration::from_millis(DEFAULT_HEARTBEAT_TIMEOUT_MILLIS);
+        let mut consensus = ConsensusMode::Bully;
+        for (key, value) in entries {
+            match key.as_str() {
+                "bind" => bind = Some(resolve(&value)?),
+                "peers" => {
+                    peers = value
+                        .split(',')
+                        .map(|peer| peer.trim())
+                        .filter(|peer| !peer.is_empty())
+                        .map(resolve)
+                        .collect::<Result<_, _>>()?;
+                }
+                "cluster_id" => cluster_id = value,
+                "difficulty" => {
+                    difficulty = match value.parse::<u32>() {
+                        Ok(difficulty) if difficulty <= MAX_DIFFICULTY => difficulty,
+                        _ => {
+                            return Err(format!(
+                                "Difficulty must be a number between 0 and {}",
+                                MAX_DIFFICULTY
+                            ))
+                        }
+                    }
+                }
+                "heartbeat_interval_ms" => heartbeat_interval = parse_millis(&key, &value)?,
+                "heartbeat_timeout_ms" => heartbeat_timeout = parse_millis(&key, &value)?,
+                "consensus" => {
+                    consensus = match value.as_str() {
+                        "bully" => ConsensusMode::Bully,
+                        "raft" => ConsensusMode::Raft,
+                        _ => return Err(format!("Unknown consensus {}, it must be bully or raft", value)),
+                    }
+                }
+                _ => return Err(format!("Unknown key {}", key)),
+            }
+        }
+
+        let bind = bind.ok_or_else(|| "The bind address is required".to_string())?;
+        if bind.ip().is_unspecified() {
+            return Err("The bind address must be the address the other nodes use".to_string());
+        }
+        if cluster_id.is_empty() {
+            return Err("The cluster_id can't be empty".to_string());
+        }
+        if heartbeat_timeout <= heartbeat_interval {
+            return Err("The heartbeat timeout must be longer than the interval".to_string());
+        }
+        peers.retain(|peer| *peer != bind);
+        peers.sort();
+        peers.dedup();
+        Ok(NodeConfig {
+            bind,
+            peers,
+            cluster_id,
+            difficulty,
+            heartbeat_interval,
+            heartbeat_timeout,
+            consensus,
+        })
+    }
+
+    /// Name used in the files of the node (logs, store, snapshot and key),
+    /// `<ip>_<port>` so nodes of different hosts can share a directory
+    pub fn node_name(&self) -> String {
+        // Las direcciones ipv6 tienen ':', que no puede ir en un nombre de archivo
+        format!("{}_{}", self.bind.ip().to_string().replace(':', "-"), self.bind.port())
+    }
+}
+
+/// Reads the `key = value` lines, skipping empty lines and `#` comments
+fn parse_entries(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) => entries.push((key.trim().to_string(), value.trim().to_string())),
+            None => return Err(format!("Line {} is not `key = value`", i + 1)),
+        }
+    }
+    Ok(entries)
+}
+
+/// Reads a positive amount of milliseconds
+fn parse_millis(key: &str, value: &str) -> Result<Duration, String> {
+    match value.parse::<u64>() {
+        Ok(millis) if millis > 0 => Ok(Duration::from_millis(millis)),
+        _ => Err(format!("{} must be a positive number of milliseconds", key)),
+    }
+}
+
+/// Resolves a `host:port` address, the nodes are identified by their ip and port
+fn resolve(addr: &str) -> Result<SocketAddr, String> {
+    addr.to_socket_addrs()
+        .map_err(|e| format!("Invalid address {}: {}", addr, e))?
+        .next()
+        .ok_or_else(|| format!("Address {} not found", addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn membership_comes_from_the_port_or_from_the_flags() {
+        let local = NodeConfig::from_args(&args("8003 4")).unwrap();
+        assert_eq!(local.bind.to_string(), "127.0.0.1:8003");
+        assert_eq!(local.peers.len(), 49);
+        assert_eq!((local.cluster_id.as_str(), local.difficulty), (DEFAULT_CLUSTER_ID, 4));
+
+        let config = NodeConfig::from_args(&args(
+            "--bind 10.0.0.1:9000 --peers 10.0.0.2:9000,10.0.0.1:9000,10.0.0.3:7000 --cluster-id fiuba",
+        ))
+        .unwrap();
+        assert_eq!(config.bind.to_string(), "10.0.0.1:9000");
+        assert_eq!(config.node_name(), "10.0.0.1_9000");
+        let peers: Vec<String> = config.peers.iter().map(|peer| peer.to_string()).collect();
+        assert_eq!(peers, vec!["10.0.0.2:9000", "10.0.0.3:7000"]);
+        assert_eq!(config.cluster_id, "fiuba");
+
+        let filename = std::env::temp_dir().join("node_config_test.conf");
+        fs::write(&filename, "# nodo 1\nbind = 10.0.0.1:9000\ndifficulty = 2\n").unwrap();
+        let line = format!("--config {} --difficulty 3", filename.display());
+        let file = NodeConfig::from_args(&args(&line)).unwrap();
+        assert_eq!(file.bind.to_string(), "10.0.0.1:9000");
+        assert_eq!((file.peers.len(), file.difficulty), (0, 3));
+        assert_eq!(file.heartbeat_timeout, Duration::from_millis(DEFAULT_HEARTBEAT_TIMEOUT_MILLIS));
+
+        let heartbeat = NodeConfig::from_args(&args("--bind 10.0.0.1:9000 --heartbeat-interval 100 --heartbeat-timeout 300")).unwrap();
+        assert_eq!(heartbeat.heartbeat_interval, Duration::from_millis(100));
+        assert_eq!(heartbeat.heartbeat_timeout, Duration::from_millis(300));
+        assert!(NodeConfig::from_args(&args("--bind 10.0.0.1:9000 --heartbeat-timeout 100")).is_err());
+        assert_eq!(heartbeat.consensus, ConsensusMode::Bully);
+        let raft = NodeConfig::from_args(&args("--bind 10.0.0.1:9000 --consensus raft")).unwrap();
+        assert_eq!(raft.consensus, ConsensusMode::Raft);
+        assert!(NodeConfig::from_args(&args("--bind 10.0.0.1:9000 --consensus paxos")).is_err());
+
+        assert!(NodeConfig::from_args(&args("--peers 10.0.0.2:9000")).is_err());
+        assert!(NodeConfig::from_args(&args("--bind 0.0.0.0:9000")).is_err());
+        assert!(NodeConfig::from_args(&args("--bind 10.0.0.1:9000 --difficulty 40")).is_err());
+        assert!(NodeConfig::from_args(&args("--bind 10.0.0.1:9000 --color blue")).is_err());
+    }
+}
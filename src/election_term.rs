@@ -0,0 +1,96 @@
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Write};
+use std::sync::Mutex;
+
+/// Term of the bully elections, it only grows. Every election uses a new term
+/// and the `ELECTION`, `OK` and `COORDINATOR` messages carry it, so a delayed
+/// `COORDINATOR` of an old election or of a node that was down is ignored.
+/// It's saved in a file so a restarted node doesn't go back to an old term
+#[derive(Debug)]
+pub struct ElectionTerm {
+    filename: String,
+    term: Mutex<u64>,
+}
+
+impl ElectionTerm {
+    /// Reads the saved term, 0 if the node never saved one
+    pub fn load(filename: &str) -> Result<Self, Error> {
+        let term = match fs::read(filename) {
+            Ok(bytes) => {
+                let bytes: [u8; 8] = bytes.as_slice().try_into().map_err(|_| {
+                    Error::new(ErrorKind::InvalidData, "the term must have 8 bytes")
+                })?;
+                u64::from_be_bytes(bytes)
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        Ok(ElectionTerm {
+            filename: filename.to_string(),
+            term: Mutex::new(term),
+        })
+    }
+
+    pub fn current(&self) -> u64 {
+        *self.term.lock().unwrap()
+    }
+
+    /// Starts a new election, returns its term
+    pub fn next(&self) -> u64 {
+        let mut term = self.term.lock().unwrap();
+        *term += 1;
+        self.save(*term);
+        *term
+    }
+
+    /// Moves to the term of a received message if it's newer.
+    /// Returns false if the message is of an older term and must be ignored
+    pub fn observe(&self, received: u64) -> bool {
+        let mut term = self.term.lock().unwrap();
+        if received < *term {
+            return false;
+        }
+        if received > *term {
+            *term = received;
+            self.save(received);
+        }
+        true
+    }
+
+    fn save(&self, term: u64) {
+        let tmp_filename = format!("{}.tmp", self.filename);
+        let saved = File::create(&tmp_filename).and_then(|mut file| {
+            file.write_all(&term.to_be_bytes())?;
+            file.sync_data()
+        });
+        // Sin el term guardado un nodo reiniciado podria aceptar un coordinador viejo
+        saved
+            .and_then(|_| fs::rename(tmp_filename, &self.filename))
+            .expect("Unable to save the election term");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_term_only_grows_and_survives_a_restart() {
+        let filename = std::env::temp_dir().join("election_term_test");
+        let filename = filename.to_str().unwrap();
+        let _ = fs::remove_file(filename);
+
+        let term = ElectionTerm::load(filename).unwrap();
+        assert_eq!(term.current(), 0);
+        assert_eq!(term.next(), 1);
+        assert!(term.observe(4));
+        assert!(term.observe(4));
+        assert!(!term.observe(3));
+        assert_eq!(term.current(), 4);
+
+        let restarted = ElectionTerm::load(filename).unwrap();
+        assert_eq!(restarted.current(), 4);
+        assert_eq!(restarted.next(), 5);
+    }
+}
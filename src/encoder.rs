@@ -1,19 +1,302 @@
+use crate::blockchain::block::Block;
+use crate::blockchain::hash::{BlockHash, HASH_SIZE};
+use crate::blockchain::record::Record;
+use crate::raft::messages::{AppendEntries, AppendResponse, VoteRequest, VoteResponse};
+use crate::utils::messages::Message;
 use std::str;
 
-const MSG_EOF: char = '\n';
+/// Version of the wire protocol, messages with another version are rejected
+pub const PROTOCOL_VERSION: u8 = 18;
 
-/// Transform string to a u8 for sent msg by socket 
-pub fn encode_to_bytes(msg: &str) -> Vec<u8> {
-    let mut message = String::from(msg.clone());
-    message.push(MSG_EOF);
-    message.into_bytes()
+/// Size of the smallest frame header: version, empty cluster id and payload length
+const FRAME_HEADER_SIZE: usize = 9;
+
+const ACQUIRE_TAG: u8 = 1;
+const RELEASE_TAG: u8 = 2;
+const LOCK_ACQUIRED_TAG: u8 = 3;
+const WHO_IS_LEADER_TAG: u8 = 4;
+const BLOCKCHAIN_TAG: u8 = 5;
+const END_TAG: u8 = 6;
+const NOOP_TAG: u8 = 7;
+const ACK_TAG: u8 = 8;
+const ELECTION_TAG: u8 = 9;
+const COORDINATOR_TAG: u8 = 10;
+const OK_TAG: u8 = 11;
+const RECORD_TAG: u8 = 12;
+const BLOCK_TAG: u8 = 13;
+const NACK_TAG: u8 = 14;
+const SNAPSHOT_CHUNK_TAG: u8 = 15;
+const JOIN_TAG: u8 = 16;
+const LEAVE_TAG: u8 = 17;
+const MEMBERS_TAG: u8 = 18;
+const HEARTBEAT_TAG: u8 = 19;
+const REQUEST_VOTE_TAG: u8 = 20;
+const VOTE_TAG: u8 = 21;
+const APPEND_ENTRIES_TAG: u8 = 22;
+const APPEND_RESPONSE_TAG: u8 = 23;
+const HEARTBEAT_ACK_TAG: u8 = 24;
+
+/// Transform a message into a frame to be sent by socket:
+/// `[version: u8][cluster id][payload length: u32][payload]`
+pub fn encode_to_bytes(msg: &Message, cluster_id: &str) -> Vec<u8> {
+    let mut payload = ByteWriter::new();
+    match msg {
+        Message::Acquire => payload.put_u8(ACQUIRE_TAG),
+        Message::Release => payload.put_u8(RELEASE_TAG),
+        Message::LockAcquired => payload.put_u8(LOCK_ACQUIRED_TAG),
+        Message::WhoIsLeader => payload.put_u8(WHO_IS_LEADER_TAG),
+        Message::Blockchain => payload.put_u8(BLOCKCHAIN_TAG),
+        Message::SnapshotChunk(index, total, chunk) => {
+            payload.put_u8(SNAPSHOT_CHUNK_TAG);
+            payload.put_u32(*index);
+            payload.put_u32(*total);
+            payload.put_bytes(chunk);
+        }
+        Message::End => payload.put_u8(END_TAG),
+        Message::Noop => payload.put_u8(NOOP_TAG),
+        Message::Ack(hash) => {
+            payload.put_u8(ACK_TAG);
+            payload.put_raw(hash.as_bytes());
+        }
+        Message::Nack(hash, reason) => {
+            payload.put_u8(NACK_TAG);
+            payload.put_raw(hash.as_bytes());
+            payload.put_str(reason);
+        }
+        Message::Election(term) => {
+            payload.put_u8(ELECTION_TAG);
+            payload.put_u64(*term);
+        }
+        Message::Coordinator(term) => {
+            payload.put_u8(COORDINATOR_TAG);
+            payload.put_u64(*term);
+        }
+        Message::Ok(term) => {
+            payload.put_u8(OK_TAG);
+            payload.put_u64(*term);
+        }
+        Message::Record(record) => {
+            payload.put_u8(RECORD_TAG);
+            record.encode(&mut payload);
+        }
+        Message::Block(block) => {
+            payload.put_u8(BLOCK_TAG);
+            block.encode(&mut payload);
+        }
+        Message::Heartbeat => payload.put_u8(HEARTBEAT_TAG),
+        Message::HeartbeatAck => payload.put_u8(HEARTBEAT_ACK_TAG),
+        Message::RequestVote(request) => {
+            payload.put_u8(REQUEST_VOTE_TAG);
+            request.encode(&mut payload);
+        }
+        Message::Vote(response) => {
+            payload.put_u8(VOTE_TAG);
+            response.encode(&mut payload);
+        }
+        Message::AppendEntries(append) => {
+            payload.put_u8(APPEND_ENTRIES_TAG);
+            append.encode(&mut payload);
+        }
+        Message::AppendResponse(response) => {
+            payload.put_u8(APPEND_RESPONSE_TAG);
+            response.encode(&mut payload);
+        }
+        Message::Join => payload.put_u8(JOIN_TAG),
+        Message::Leave => payload.put_u8(LEAVE_TAG),
+        Message::Members(members) => {
+            payload.put_u8(MEMBERS_TAG);
+            payload.put_u32(members.len() as u32);
+            for member in members {
+                payload.put_str(member);
+            }
+        }
+    }
+
+    let mut frame = ByteWriter::new();
+    frame.put_u8(PROTOCOL_VERSION);
+    frame.put_str(cluster_id);
+    frame.put_bytes(&payload.into_bytes());
+    frame.into_bytes()
+}
+
+/// Transform a frame read from socket into a message,
+/// frames of other clusters are rejected
+pub fn decode_from_bytes(frame: &[u8], cluster_id: &str) -> Result<Message, String> {
+    if frame.len() < FRAME_HEADER_SIZE {
+        return Err(format!("Frame too short ({} bytes)", frame.len()));
+    }
+    let mut frame_reader = ByteReader::new(frame);
+    let version = frame_reader.get_u8()?;
+    if version != PROTOCOL_VERSION {
+        return Err(format!("Unsupported protocol version {}", version));
+    }
+    let frame_cluster_id = frame_reader.get_str()?;
+    if frame_cluster_id != cluster_id {
+        return Err(format!("Message of the cluster {}", frame_cluster_id));
+    }
+    let payload = frame_reader.get_bytes()?;
+    if !frame_reader.is_empty() {
+        return Err("Frame length doesn't match its payload".to_string());
+    }
+
+    let mut reader = ByteReader::new(payload);
+    let msg = match reader.get_u8()? {
+        ACQUIRE_TAG => Message::Acquire,
+        RELEASE_TAG => Message::Release,
+        LOCK_ACQUIRED_TAG => Message::LockAcquired,
+        WHO_IS_LEADER_TAG => Message::WhoIsLeader,
+        BLOCKCHAIN_TAG => Message::Blockchain,
+        SNAPSHOT_CHUNK_TAG => Message::SnapshotChunk(
+            reader.get_u32()?,
+            reader.get_u32()?,
+            reader.get_bytes()?.to_vec(),
+        ),
+        END_TAG => Message::End,
+        NOOP_TAG => Message::Noop,
+        ACK_TAG => Message::Ack(BlockHash::from_slice(reader.get_raw(HASH_SIZE)?)?),
+        NACK_TAG => Message::Nack(
+            BlockHash::from_slice(reader.get_raw(HASH_SIZE)?)?,
+            reader.get_str()?,
+        ),
+        ELECTION_TAG => Message::Election(reader.get_u64()?),
+        COORDINATOR_TAG => Message::Coordinator(reader.get_u64()?),
+        OK_TAG => Message::Ok(reader.get_u64()?),
+        RECORD_TAG => Message::Record(Box::new(Record::decode(&mut reader)?)),
+        BLOCK_TAG => Message::Block(Box::new(Block::decode(&mut reader)?)),
+        HEARTBEAT_TAG => Message::Heartbeat,
+        HEARTBEAT_ACK_TAG => Message::HeartbeatAck,
+        REQUEST_VOTE_TAG => Message::RequestVote(VoteRequest::decode(&mut reader)?),
+        VOTE_TAG => Message::Vote(VoteResponse::decode(&mut reader)?),
+        APPEND_ENTRIES_TAG => Message::AppendEntries(Box::new(AppendEntries::decode(&mut reader)?)),
+        APPEND_RESPONSE_TAG => Message::AppendResponse(AppendResponse::decode(&mut reader)?),
+        JOIN_TAG => Message::Join,
+        LEAVE_TAG => Message::Leave,
+        MEMBERS_TAG => {
+            let mut members = Vec::new();
+            for _ in 0..reader.get_u32()? {
+                members.push(reader.get_str()?);
+            }
+            Message::Members(members)
+        }
+        tag => return Err(format!("Unknown message type {}", tag)),
+    };
+    if !reader.is_empty() {
+        return Err("Unexpected bytes at the end of the message".to_string());
+    }
+    Ok(msg)
+}
+
+/// Serializes primitive values into a byte buffer (big endian)
+#[derive(Default)]
+pub struct ByteWriter {
+    buf: Vec<u8>,
 }
 
-/// Transform u8 to string for read msg from socket
-pub fn decode_from_bytes(payload: Vec<u8>) -> String {
-    let data = str::from_utf8(&payload)
-        .unwrap()
-        .split(MSG_EOF)
-        .collect::<Vec<&str>>()[0];
-    data.to_string()
+impl ByteWriter {
+    pub fn new() -> Self {
+        ByteWriter { buf: Vec::new() }
+    }
+
+    pub fn put_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn put_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_i32(&mut self, value: i32) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// Writes the length of the slice followed by its content
+    pub fn put_bytes(&mut self, value: &[u8]) {
+        self.put_u32(value.len() as u32);
+        self.buf.extend_from_slice(value);
+    }
+
+    /// Writes the slice as it is, the reader has to know its length
+    pub fn put_raw(&mut self, value: &[u8]) {
+        self.buf.extend_from_slice(value);
+    }
+
+    pub fn put_str(&mut self, value: &str) {
+        self.put_bytes(value.as_bytes());
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads back the values written by a `ByteWriter`
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, pos: 0 }
+    }
+
+    /// Takes the next `len` bytes or fails if the buffer is too short
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < len {
+            return Err(format!(
+                "Unexpected end of data: needed {} bytes at offset {}",
+                len, self.pos
+            ));
+        }
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    pub fn get_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    pub fn get_i32(&mut self) -> Result<i32, String> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(i32::from_be_bytes(bytes))
+    }
+
+    pub fn get_raw(&mut self, len: usize) -> Result<&'a [u8], String> {
+        self.take(len)
+    }
+
+    pub fn get_bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.get_u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn get_str(&mut self) -> Result<String, String> {
+        let bytes = self.get_bytes()?;
+        str::from_utf8(bytes)
+            .map(|s| s.to_string())
+            .map_err(|e| format!("Invalid utf-8 string: {}", e))
+    }
+
+    /// True when every byte has been consumed
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
 }
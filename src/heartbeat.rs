@@ -0,0 +1,170 @@
+use crate::membership::Membership;
+use crate::utils::logger::Logger;
+use crate::utils::messages::Message;
+use crate::utils::socket::Socket;
+
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What a follower knows about the leader from the time since its last message
+#[derive(Debug, PartialEq)]
+pub enum LeaderStatus {
+    /// The leader sent a message before the timeout
+    Alive,
+    /// No message during the timeout, the leader is asked again
+    Suspected,
+    /// No message during two timeouts, a new leader is elected
+    Confirmed,
+}
+
+/// Status of the leader after `elapsed` without any of its messages
+pub fn leader_status(elapsed: Duration, timeout: Duration) -> LeaderStatus {
+    if elapsed < timeout {
+        LeaderStatus::Alive
+    } else if elapsed < timeout * 2 {
+        LeaderStatus::Suspected
+    } else {
+        LeaderStatus::Confirmed
+    }
+}
+
+/// Responsible for sending a heartbeat to every node every `interval`
+/// while this node is the leader, and for detecting that the leader is down
+/// while it's a follower, even if nobody is submitting records.
+/// The leader is suspected when none of its messages arrive during `timeout`
+/// and confirmed down after another `timeout`, then the election starts.
+/// In the same way the leader removes the members without answers during two `timeout`
+pub struct HeartbeatMonitor {
+    pub my_address: Arc<RwLock<String>>,
+    pub leader_addr: Arc<RwLock<Option<String>>>,
+    pub socket: Socket,
+    pub members: Arc<Membership>,
+    pub last_heartbeat: Arc<Mutex<Instant>>,
+    pub leader_down: Arc<(Mutex<bool>, Condvar)>,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub logger: Arc<Logger>,
+    pub alive: Arc<RwLock<bool>>,
+}
+
+impl HeartbeatMonitor {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        my_address: Arc<RwLock<String>>,
+        leader_addr: Arc<RwLock<Option<String>>>,
+        socket: Socket,
+        members: Arc<Membership>,
+        last_heartbeat: Arc<Mutex<Instant>>,
+        leader_down: Arc<(Mutex<bool>, Condvar)>,
+        interval: Duration,
+        timeout: Duration,
+        logger: Arc<Logger>,
+        alive: Arc<RwLock<bool>>,
+    ) -> Self {
+        HeartbeatMonitor {
+            my_address,
+            leader_addr,
+            socket,
+            members,
+            last_heartbeat,
+            leader_down,
+            interval,
+            timeout,
+            logger,
+            alive,
+        }
+    }
+
+    pub fn run(&mut self) {
+        let mut suspected = false;
+        let mut leading = false;
+        while *self.alive.read().unwrap() {
+            thread::sleep(self.interval);
+
+            let leader = match (*self.leader_addr.read().unwrap()).clone() {
+                Some(leader) => leader,
+                None => continue,
+            };
+            if leader == *self.my_address.read().unwrap() {
+                // Recien es lider, hasta ahora solo recibia mensajes del lider anterior
+                if !leading {
+                    leading = true;
+                    self.members.seen_all(Instant::now());
+                }
+                self.send_heartbeats();
+                self.expire_members();
+                continue;
+            }
+            leading = false;
+            // Ya hay una eleccion en curso
+            if *self.leader_down.0.lock().unwrap() {
+                suspected = false;
+                continue;
+            }
+
+            let elapsed = self.last_heartbeat.lock().unwrap().elapsed();
+            match leader_status(elapsed, self.timeout) {
+                LeaderStatus::Alive => suspected = false,
+                LeaderStatus::Suspected if !suspected => {
+                    suspected = true;
+                    self.logger.info(format!(
+                        "Leader {} suspected down: no message in {} ms",
+                        leader,
+                        elapsed.as_millis()
+                    ));
+                    // Si sigue vivo responde con un COORDINATOR, sin reenviar la blockchain
+                    self.socket.send_to(Message::Heartbeat, leader);
+                }
+                LeaderStatus::Suspected => {}
+                LeaderStatus::Confirmed => {
+                    suspected = false;
+                    self.logger.info(format!(
+                        "Leader {} confirmed down: no message in {} ms, starting an election",
+                        leader,
+                        elapsed.as_millis()
+                    ));
+                    let (lock, cvar) = &*self.leader_down;
+                    *lock.lock().unwrap() = true;
+                    cvar.notify_all();
+                }
+            }
+        }
+    }
+
+    fn send_heartbeats(&mut self) {
+        for node in self.members.members() {
+            self.socket.send_to(Message::Heartbeat, node);
+        }
+    }
+
+    /// Removes the members that stopped answering the heartbeats (they crashed
+    /// without a `LEAVE`) and sends the new list to the others
+    fn expire_members(&mut self) {
+        let expired = self.members.expire(Instant::now(), self.timeout * 2);
+        if expired.is_empty() {
+            return;
+        }
+        for node in expired.iter() {
+            self.logger.info(format!("Node {} didn't answer the heartbeats and was removed", node));
+        }
+        let members = self.members.with_me();
+        for node in self.members.members() {
+            self.socket.send_to(Message::Members(members.clone()), node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_leader_is_suspected_and_then_confirmed_down() {
+        let timeout = Duration::from_millis(2000);
+        assert_eq!(leader_status(Duration::from_millis(1999), timeout), LeaderStatus::Alive);
+        assert_eq!(leader_status(Duration::from_millis(2000), timeout), LeaderStatus::Suspected);
+        assert_eq!(leader_status(Duration::from_millis(3999), timeout), LeaderStatus::Suspected);
+        assert_eq!(leader_status(Duration::from_millis(4000), timeout), LeaderStatus::Confirmed);
+    }
+}
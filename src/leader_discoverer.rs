@@ -1,6 +1,8 @@
-use crate::utils::messages::*;
+use crate::utils::messages::Message;
 use crate::utils::socket::Socket;
 use crate::utils::logger::Logger;
+use crate::membership::Membership;
+use crate::election_term::ElectionTerm;
 
 use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::time;
@@ -16,7 +18,8 @@ pub struct LeaderDiscoverer {
     pub leader_addr: Arc<RwLock<Option<String>>>,
     pub my_address: Arc<RwLock<String>>,
     pub socket: Socket,
-    pub other_nodes: Arc<Vec<String>>,
+    pub members: Arc<Membership>,
+    pub term: Arc<ElectionTerm>,
     pub logger: Arc<Logger>
 }
 
@@ -26,7 +29,8 @@ impl LeaderDiscoverer {
         leader_addr: Arc<RwLock<Option<String>>>,
         my_address: Arc<RwLock<String>>,
         socket: Socket,
-        other_nodes: Arc<Vec<String>>,
+        members: Arc<Membership>,
+        term: Arc<ElectionTerm>,
         logger: Arc<Logger>
     ) -> Self {
         LeaderDiscoverer {
@@ -34,7 +38,8 @@ impl LeaderDiscoverer {
             leader_addr,
             my_address,
             socket,
-            other_nodes,
+            members,
+            term,
             logger
         }
     }
@@ -44,10 +49,9 @@ impl LeaderDiscoverer {
     // envia mensajes para tratar de encontrar a otro lider
     // si falla (timeout) entonces se setea a si mismo.
     pub fn run(&mut self) -> () {
-        for node in &*self.other_nodes {
+        for node in self.members.members() {
             self.socket
-                .send_to(WHO_IS_LEADER.to_string(), node.clone())
-                .unwrap();
+                .send_to(Message::WhoIsLeader, node);
         }
 
         let time = time::Instant::now();
@@ -69,10 +73,14 @@ impl LeaderDiscoverer {
                 if let Ok(mut leader_addr_mut) = self.leader_addr.write() {
                     *leader_addr_mut = Some((*self.my_address.read().unwrap()).clone());
 
-                    for node in &*self.other_nodes {
+                    // Nadie respondio, los nodos que esten vivos se van a unir
+                    // con un JOIN al recibir el COORDINATOR
+                    let nodes = self.members.members();
+                    self.members.replace(Vec::new());
+                    let term = self.term.next();
+                    for node in nodes {
                         self.socket
-                            .send_to(COORDINATOR.to_string(), node.clone())
-                            .unwrap();
+                            .send_to(Message::Coordinator(term), node);
                     }
                 }
                 *leader_found = true;
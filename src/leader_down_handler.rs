@@ -1,19 +1,24 @@
-use crate::utils::messages::*;
+use crate::utils::messages::Message;
 use crate::utils::socket::Socket;
 use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::time::Duration;
 use crate::utils::logger::Logger;
+use crate::membership::Membership;
+use crate::election_term::ElectionTerm;
+use std::net::SocketAddr;
 
-const MAX_NODES: u32 = 50;
 const ELECTION_TIMEOUT_SECS: u64 = 1;
 
 
 /// Responsible for check if the leader is down
 /// and found a new node to be the leader
-/// and notify all node for set the new leader addr
+/// and notify all node for set the new leader addr.
+/// The node with the greatest address (ip and then port) wins the election
 pub struct LeaderDownHandler {
     pub my_address: Arc<RwLock<String>>,
     pub socket: Socket,
+    pub members: Arc<Membership>,
+    pub term: Arc<ElectionTerm>,
     pub election_condvar: Arc<(Mutex<Option<String>>, Condvar)>,
     pub leader_down: Arc<(Mutex<bool>, Condvar)>,
     pub running_bully: Arc<Mutex<bool>>,
@@ -22,9 +27,12 @@ pub struct LeaderDownHandler {
 }
 
 impl LeaderDownHandler {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         my_address: Arc<RwLock<String>>,
         socket: Socket,
+        members: Arc<Membership>,
+        term: Arc<ElectionTerm>,
         election_condvar: Arc<(Mutex<Option<String>>, Condvar)>,
         leader_down: Arc<(Mutex<bool>, Condvar)>,
         running_bully: Arc<Mutex<bool>>,
@@ -34,6 +42,8 @@ impl LeaderDownHandler {
         LeaderDownHandler {
             my_address,
             socket,
+            members,
+            term,
             election_condvar,
             leader_down,
             running_bully,
@@ -65,10 +75,11 @@ impl LeaderDownHandler {
     }
 
     fn run_bully_algorithm(&mut self) {
-        self.logger.info(format!("Running bully algorithm"));
+        let term = self.term.next();
+        self.logger.info(format!("Running bully algorithm, term {}", term));
 
         for node in self.find_upper_sockets() {
-            self.socket.send_to(ELECTION.to_string(), node).unwrap();
+            self.socket.send_to(Message::Election(term), node);
         }
         let current_value;
 
@@ -83,7 +94,10 @@ impl LeaderDownHandler {
         }
 
         if current_value.is_none() {
-            let mut addr_list = self.build_addr_list();
+            // Si mientras esperabamos llego un term mayor, lo usamos
+            // para que nadie descarte el coordinador
+            let term = self.term.current();
+            let mut addr_list = self.members.members();
             // FIXME. Agregamos nuestra direccion a la lista
             // para poder setearnos en nuestro estado interno
             // que somos el coordinador.
@@ -91,41 +105,23 @@ impl LeaderDownHandler {
 
             for n_addr in addr_list {
                 self.socket
-                    .send_to(COORDINATOR.to_string(), n_addr)
-                    .unwrap();
+                    .send_to(Message::Coordinator(term), n_addr);
             }
         }
         // Limpiamos la variable de condición 
         *lock.lock().unwrap() = None;
     }
 
-    fn build_addr_list(&self) -> Vec<String> {
-        let mut addrs = vec![];
-        for i in 0..MAX_NODES {
-            let addr = format!("127.0.0.1:{}", 8000 + i);
-            if &addr != &*self.my_address.read().unwrap() {
-                addrs.push(addr);
-            }
-        }
-        addrs
-    }
-
-    /// Get port from addrs
-    fn get_port_from_addr(&self, addr: String) -> u32 {
-        addr.split(":").collect::<Vec<&str>>()[1]
-            .parse::<u32>()
-            .unwrap()
-    }
-
-    /// Find port bigger that my addr port 
+    /// Find the nodes with an address bigger than my address
     fn find_upper_sockets(&self) -> Vec<String> {
+        let my_address: SocketAddr = self.my_address.read().unwrap().parse().unwrap();
         let mut upper_nodes = vec![];
 
-        for n_addr in self.build_addr_list() {
-            if self.get_port_from_addr((*self.my_address.read().unwrap()).clone())
-                < self.get_port_from_addr(n_addr.clone())
-            {
-                upper_nodes.push(n_addr);
+        for n_addr in self.members.members() {
+            if let Ok(addr) = n_addr.parse::<SocketAddr>() {
+                if my_address < addr {
+                    upper_nodes.push(n_addr);
+                }
             }
         }
         upper_nodes
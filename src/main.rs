@@ -3,50 +3,125 @@ mod node;
 mod blockchain;
 
 use std::env;
+use std::path::Path;
 use std::process;
 
+mod block_sealer;
+mod config;
+mod election_term;
 mod encoder;
+mod heartbeat;
 mod leader_discoverer;
 mod leader_down_handler;
+mod membership;
+mod raft;
 mod stdin_reader;
 mod utils;
+use config::NodeConfig;
 use utils::logger::Logger;
+use blockchain::block_store::BlockStore;
+use blockchain::genesis::GenesisConfig;
+use blockchain::signature::load_or_generate_key;
+use blockchain::snapshot::SnapshotStore;
 use std::sync::{Arc};
 
 const MESSAGE_LOGGER_ERROR: &str = "Unable to open logger file ";
+const MESSAGE_STORE_ERROR: &str = "Unable to open blockchain store file ";
+const MESSAGE_KEY_ERROR: &str = "Unable to load the key file ";
+const MESSAGE_GENESIS_ERROR: &str = "Invalid network configuration file ";
+const MESSAGE_CONFIG_ERROR: &str = "Invalid node configuration:";
+const MESSAGE_USAGE: &str = "Usage: app <port> [difficulty]
+       app [--config <file>] [--bind <host:port>] [--peers <host:port>,...] [--cluster-id <id>] [--difficulty <n>]
+           [--heartbeat-interval <ms>] [--heartbeat-timeout <ms>] [--consensus bully|raft]";
 
+/// Configuration of the network used to create the genesis block, it is optional
+const GENESIS_CONFIG_FILE: &str = "genesis.conf";
 
-fn port_missing() -> i32 {
-    println!("Number of port must be specified");
-    return -1;
+
+/// Reads where the node listens and which nodes form the cluster
+fn load_node_config(args: &[String]) -> NodeConfig {
+    match NodeConfig::from_args(args) {
+        Ok(config) => config,
+        Err(e) => {
+            println!("{} {}\n{}", MESSAGE_CONFIG_ERROR, e, MESSAGE_USAGE);
+            process::exit(-1);
+        }
+    }
+}
+
+/// Reads the configuration of the network if the file exists
+fn load_genesis_config() -> Option<GenesisConfig> {
+    if !Path::new(GENESIS_CONFIG_FILE).exists() {
+        return None;
+    }
+    match GenesisConfig::from_file(GENESIS_CONFIG_FILE) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            println!("{} {:?}: {}", MESSAGE_GENESIS_ERROR, GENESIS_CONFIG_FILE, e);
+            process::exit(-1);
+        }
+    }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        process::exit(port_missing());
-    }
-    let logger = match Logger::new(&format!("log_{}",&args[1])) {
+    let config = load_node_config(&args[1..]);
+    let name = config.node_name();
+
+    let logger = match Logger::new(&format!("log_{}", name)) {
         Ok(logger) => Arc::new(logger),
         Err(e) => {
-            println!("{} {:?}: {}", MESSAGE_LOGGER_ERROR, &args[1], e);
+            println!("{} {:?}: {}", MESSAGE_LOGGER_ERROR, name, e);
             process::exit(-1);
         }
     };
-    println!("Logging messages will be saved to: log_{:?}.", args[1]);
+    println!("Logging messages will be saved to: log_{:?}.", name);
 
-    let blockchain_filename = format!("log_{}_blockchain", args[1]);
+    let blockchain_filename = format!("log_{}_blockchain", name);
 
     let blockchain_logger = match Logger::new(&blockchain_filename) {
         Ok(logger) => Arc::new(logger),
         Err(e) => {
-            println!("{} {:?}: {}", MESSAGE_LOGGER_ERROR, &args[1], e);
+            println!("{} {:?}: {}", MESSAGE_LOGGER_ERROR, name, e);
             process::exit(-1);
         }
     };
 
     println!("Detailed blockchain will be logged in: {:?}\n", blockchain_filename);
 
-    let mut node = node::Node::new(&args[1], logger, blockchain_logger);
+    let store_filename = format!("store_{}_blockchain", name);
+
+    let store = match BlockStore::open(&store_filename) {
+        Ok(store) => Arc::new(store),
+        Err(e) => {
+            println!("{} {:?}: {}", MESSAGE_STORE_ERROR, store_filename, e);
+            process::exit(-1);
+        }
+    };
+
+    let snapshot_filename = format!("snapshot_{}_blockchain", name);
+    let snapshots = Arc::new(SnapshotStore::new(&snapshot_filename));
+
+    let key_filename = format!("key_{}", name);
+
+    let signing_key = match load_or_generate_key(&key_filename) {
+        Ok(key) => Arc::new(key),
+        Err(e) => {
+            println!("{} {:?}: {}", MESSAGE_KEY_ERROR, key_filename, e);
+            process::exit(-1);
+        }
+    };
+
+    let genesis_config = load_genesis_config();
+
+    let mut node = node::Node::new(
+        config,
+        logger,
+        blockchain_logger,
+        store,
+        snapshots,
+        signing_key,
+        genesis_config,
+    );
     node.run();
 }
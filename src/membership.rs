@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// Nodes of the cluster that are alive, without this node.
+/// It starts with the peers of the configuration and then the leader
+/// keeps it updated with the nodes that join and leave the cluster
+/// and sends it to every node (`Message::Members`).
+/// The leader also removes the nodes that stop answering its heartbeats,
+/// a node that crashes doesn't send `Message::Leave`
+#[derive(Debug)]
+pub struct Membership {
+    /// Address of this node, it's never part of the list
+    my_address: String,
+
+    /// Address of every other node, sorted
+    members: RwLock<Vec<String>>,
+
+    /// When the last message of every member arrived
+    last_seen: RwLock<HashMap<String, Instant>>,
+}
+
+impl Membership {
+    pub fn new(my_address: String, peers: Vec<String>) -> Self {
+        let membership = Membership {
+            my_address,
+            members: RwLock::new(Vec::new()),
+            last_seen: RwLock::new(HashMap::new()),
+        };
+        membership.replace(peers);
+        membership
+    }
+
+    /// Returns the address of every other node
+    pub fn members(&self) -> Vec<String> {
+        self.members.read().unwrap().clone()
+    }
+
+    /// Adds a node, returns false if it was already a member
+    pub fn add(&self, addr: String) -> bool {
+        if addr == self.my_address {
+            return false;
+        }
+        let mut members = self.members.write().unwrap();
+        match members.binary_search(&addr) {
+            Ok(_) => false,
+            Err(position) => {
+                self.last_seen.write().unwrap().insert(addr.clone(), Instant::now());
+                members.insert(position, addr);
+                true
+            }
+        }
+    }
+
+    /// Removes a node, returns false if it wasn't a member
+    pub fn remove(&self, addr: &str) -> bool {
+        let mut members = self.members.write().unwrap();
+        let before = members.len();
+        members.retain(|member| member != addr);
+        self.last_seen.write().unwrap().remove(addr);
+        members.len() != before
+    }
+
+    /// A message of `addr` arrived at `now`, it's ignored if it's not a member
+    pub fn seen(&self, addr: &str, now: Instant) {
+        if let Some(last_seen) = self.last_seen.write().unwrap().get_mut(addr) {
+            *last_seen = now;
+        }
+    }
+
+    /// Counts every member as seen at `now`, used when this node becomes the leader
+    /// because until then it only got the messages of the old leader
+    pub fn seen_all(&self, now: Instant) {
+        for last_seen in self.last_seen.write().unwrap().values_mut() {
+            *last_seen = now;
+        }
+    }
+
+    /// Removes the members without messages during `timeout` before `now`, returns them
+    pub fn expire(&self, now: Instant, timeout: Duration) -> Vec<String> {
+        let mut members = self.members.write().unwrap();
+        let mut last_seen = self.last_seen.write().unwrap();
+        let expired: Vec<String> = members
+            .iter()
+            .filter(|member| match last_seen.get(*member) {
+                Some(seen) => now.saturating_duration_since(*seen) >= timeout,
+                None => true,
+            })
+            .cloned()
+            .collect();
+        members.retain(|member| !expired.contains(member));
+        for member in expired.iter() {
+            last_seen.remove(member);
+        }
+        expired
+    }
+
+    /// Replaces the list with the one sent by the leader
+    pub fn replace(&self, mut members: Vec<String>) {
+        members.retain(|member| *member != self.my_address);
+        members.sort();
+        members.dedup();
+        let now = Instant::now();
+        let mut current = self.members.write().unwrap();
+        let mut last_seen = self.last_seen.write().unwrap();
+        last_seen.retain(|member, _| members.contains(member));
+        for member in members.iter() {
+            last_seen.entry(member.clone()).or_insert(now);
+        }
+        *current = members;
+    }
+
+    /// Every node of the cluster, including this one, to be sent to the other nodes
+    pub fn with_me(&self) -> Vec<String> {
+        let mut members = self.members();
+        members.push(self.my_address.clone());
+        members.sort();
+        members
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_list_never_contains_this_node() {
+        let me = "127.0.0.1:8001".to_string();
+        let peers = vec!["127.0.0.1:8002".to_string(), me.clone()];
+        let membership = Membership::new(me.clone(), peers);
+        assert_eq!(membership.members(), vec!["127.0.0.1:8002"]);
+
+        assert!(membership.add("127.0.0.1:8000".into()));
+        assert!(!membership.add("127.0.0.1:8000".into()));
+        assert!(!membership.add(me));
+        assert_eq!(membership.members(), vec!["127.0.0.1:8000", "127.0.0.1:8002"]);
+        assert_eq!(membership.with_me().len(), 3);
+
+        assert!(membership.remove("127.0.0.1:8002"));
+        assert!(!membership.remove("127.0.0.1:8002"));
+        membership.replace(membership.with_me());
+        assert_eq!(membership.members(), vec!["127.0.0.1:8000"]);
+    }
+
+    #[test]
+    fn members_that_stop_answering_expire() {
+        let me = "127.0.0.1:8001".to_string();
+        let peers = vec!["127.0.0.1:8002".to_string(), "127.0.0.1:8003".to_string()];
+        let membership = Membership::new(me, peers);
+        let timeout = Duration::from_secs(4);
+        let start = Instant::now();
+        membership.seen_all(start);
+
+        membership.seen("127.0.0.1:8002", start + Duration::from_secs(3));
+        // Un nodo que no es miembro no se agrega por enviar un mensaje
+        membership.seen("127.0.0.1:8009", start + Duration::from_secs(3));
+        assert!(membership.expire(start + Duration::from_secs(3), timeout).is_empty());
+        assert_eq!(membership.expire(start + Duration::from_secs(5), timeout), vec!["127.0.0.1:8003"]);
+        assert_eq!(membership.members(), vec!["127.0.0.1:8002"]);
+
+        assert_eq!(membership.expire(start + Duration::from_secs(7), timeout), vec!["127.0.0.1:8002"]);
+        assert!(membership.members().is_empty());
+
+        // Si vuelve a unirse se cuenta desde que se agrega
+        assert!(membership.add("127.0.0.1:8003".into()));
+        assert!(membership.expire(Instant::now(), timeout).is_empty());
+    }
+}
@@ -1,26 +1,44 @@
+use crate::block_sealer::BlockSealer;
+use crate::config::{ConsensusMode, NodeConfig};
+use crate::election_term::ElectionTerm;
 use crate::blockchain::block::Block;
+use crate::blockchain::block_store::BlockStore;
 use crate::blockchain::blockchain::Blockchain;
+use crate::blockchain::error::BlockchainError;
+use crate::blockchain::state_machine::StateMachine;
+use crate::blockchain::genesis::GenesisConfig;
+use crate::blockchain::hash::BlockHash;
+use crate::blockchain::snapshot::{SnapshotChunks, SnapshotStore, StateSnapshot};
+use crate::heartbeat::HeartbeatMonitor;
 use crate::leader_discoverer::LeaderDiscoverer;
 use crate::leader_down_handler::LeaderDownHandler;
-use crate::stdin_reader::StdinReader;
-use crate::utils::messages::*;
+use crate::membership::Membership;
+use crate::raft::consensus::{apply_committed, Raft, Step};
+use crate::raft::store::RaftStore;
+use crate::raft::timer::RaftTimer;
+use crate::stdin_reader::{LeaderHandles, StdinReader};
+use crate::utils::messages::{LeaderResponse, Message};
 use crate::utils::socket::Socket;
 use crate::utils::logger::Logger;
-use crate::blockchain::record::{Record, RecordData};
+use crate::blockchain::record::Record;
 
+use ed25519_dalek::SigningKey;
 use std::net::{SocketAddr, UdpSocket};
 use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::thread::{self, JoinHandle};
 use std_semaphore::Semaphore;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-const MAX_NODES: u32 = 50;
 const MUTEX_HOLD_TIMEOUT_SECS: u64 = 30;
 
+/// Max size of each part of a snapshot sent to a new node (it has to fit in a datagram)
+const SNAPSHOT_CHUNK_SIZE: usize = 32 * 1024;
+
 pub struct Node {
     pub my_address: Arc<RwLock<String>>,
     pub socket: Socket,
-    pub other_nodes: Arc<Vec<String>>,
+    // Nodos vivos del cluster, los mantiene el lider con los JOIN y LEAVE
+    pub members: Arc<Membership>,
     pub leader_addr: Arc<RwLock<Option<String>>>,
     pub blockchain: Arc<RwLock<Blockchain>>,
     pub leader_condvar: Arc<(Mutex<bool>, Condvar)>,
@@ -31,6 +49,19 @@ pub struct Node {
     pub logger: Arc<Logger>,
     pub blockchain_logger: Arc<Logger>,
 
+    // Copia en disco de la blockchain, se reconstruye al reiniciar el nodo
+    pub store: Arc<BlockStore>,
+
+    // Ultimo snapshot del estado, permite sincronizar sin todos los bloques
+    pub snapshots: Arc<SnapshotStore>,
+
+    // Clave privada del nodo, firma los registros que se envian al lider
+    pub signing_key: Arc<SigningKey>,
+
+    // Configuracion de la red leida del archivo, si el nodo tiene una.
+    // Se usa para crear el bloque genesis y para rechazar blockchains de otra red
+    pub genesis_config: Option<GenesisConfig>,
+
     // Cantidad de nodos que tomaron el mutex pero que no lo liberaron
     // debería ser en el peor de los casos 1 (si no hacemos un panic).
     pub not_released_nodes: Arc<RwLock<i32>>,
@@ -38,59 +69,185 @@ pub struct Node {
     // El nodo esta vivo (no se hizo `close`)
     pub alive: Arc<RwLock<bool>>,
 
-    // Convar para detectar mensajes ack
-    pub msg_ack_cv: Arc<(Mutex<bool>, Condvar)>,
+    // Convar para detectar mensajes ack/nack, guarda la respuesta del lider
+    // (Err con el motivo si el registro fue rechazado)
+    pub msg_ack_cv: Arc<(Mutex<LeaderResponse>, Condvar)>,
 
     pub leader_down: Arc<(Mutex<bool>, Condvar)>,
+
+    // Convar para avisar que hay nuevos registros pendientes para sellar
+    pub pending_cv: Arc<(Mutex<bool>, Condvar)>,
+
     pub running_bully: Arc<Mutex<bool>>,
 
+    // Term de la ultima eleccion, se descartan los mensajes del bully de terms viejos
+    pub term: Arc<ElectionTerm>,
+
+    // Consenso de raft, None si el cluster usa el algoritmo bully y el mutex distribuido
+    pub raft: Option<Arc<Mutex<Raft>>>,
+
+    // Momento del ultimo mensaje recibido del lider, si tarda mucho el lider se cayo
+    pub last_heartbeat: Arc<Mutex<Instant>>,
+
+    // Cada cuanto el lider envia un heartbeat y cuanto se lo espera
+    pub heartbeat_interval: Duration,
+    pub heartbeat_timeout: Duration,
+
     pub running_threads: Vec<Option<JoinHandle<()>>>,
 }
 
-fn build_addr_list(skip_addr: &String) -> Vec<String> {
-    let mut addrs = vec![];
-    for i in 0..MAX_NODES {
-        let addr = format!("127.0.0.1:{}", 8000 + i);
-        if &addr != skip_addr {
-            addrs.push(addr);
+/// Rebuilds the blockchain saved in the store replaying every block.
+/// If the stored blocks don't start at the genesis block (the node was synced
+/// from a snapshot) it starts from the saved snapshot and replays the blocks after it.
+/// Blocks after the first one that can't be appended are dropped from the store
+fn restore_blockchain(store: &BlockStore, snapshots: &SnapshotStore, logger: &Logger, difficulty: u32) -> Blockchain {
+    let empty = Blockchain::with_difficulty(difficulty);
+    let (blocks, discarded) = match store.load() {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            // El archivo no se puede usar (por ejemplo es de otra version), se guarda
+            // aparte para no perder los bloques y se empieza de nuevo
+            let moved = store.set_aside().unwrap_or_else(|move_err| {
+                panic!("Unable to read the blockchain store: {}. Unable to move it: {}", err, move_err)
+            });
+            logger.info(format!(
+                "Unable to read the blockchain store: {}. It was moved to {}, starting with an empty blockchain",
+                err, moved
+            ));
+            return empty;
         }
+    };
+    if discarded {
+        logger.info("The last entry of the blockchain store was corrupted and was discarded".to_string());
     }
-    addrs
+
+    let starts_at_genesis = matches!(blocks.first(), Some(block) if block.header.height == 0);
+    let mut blockchain = if starts_at_genesis {
+        empty
+    } else {
+        restore_snapshot(snapshots, logger, difficulty).unwrap_or(empty)
+    };
+
+    let stored_blocks = blocks.len();
+    for block in blocks {
+        // Los bloques que ya estan en el snapshot no se vuelven a ejecutar
+        let height = block.header.height;
+        if height < blockchain.len() as u64 {
+            continue;
+        }
+        if let Err(err) = blockchain.append_block(block) {
+            logger.info(format!("Stored block {} is invalid: {}", height, err));
+            if let Err(err) = store.rewrite(&blockchain.blocks) {
+                logger.info(format!("Unable to rewrite the blockchain store: {}", err));
+            }
+            break;
+        }
+    }
+    logger.info(format!(
+        "Restored {} of {} blocks from the blockchain store",
+        blockchain.blocks.len(),
+        stored_blocks
+    ));
+    blockchain
 }
 
-impl Node {
-    pub fn new(port_number: &str, logger: Arc<Logger>, blockchain_logger: Arc<Logger>) -> Self {
-        let port_number = port_number.parse::<u32>().unwrap();
-        if port_number < 8000 || port_number > (8000 + MAX_NODES) {
-            panic!(
-                "Port number must be between {} and {}",
-                8000,
-                8000 + MAX_NODES
-            );
+/// Builds a blockchain from the saved snapshot, None if there is no valid snapshot
+fn restore_snapshot(snapshots: &SnapshotStore, logger: &Logger, difficulty: u32) -> Option<Blockchain> {
+    let snapshot = match snapshots.load() {
+        Ok(snapshot) => snapshot?,
+        Err(err) => {
+            logger.info(format!("Unable to read the snapshot: {}", err));
+            return None;
+        }
+    };
+    let height = snapshot.height();
+    match Blockchain::from_snapshot(snapshot, Vec::new(), difficulty, None) {
+        Ok(blockchain) => {
+            logger.info(format!("Restored the snapshot of block {}", height));
+            Some(blockchain)
         }
-        let my_address: String = format!("127.0.0.1:{}", port_number);
+        Err(err) => {
+            logger.info(format!("The saved snapshot is invalid: {}", err));
+            None
+        }
+    }
+}
 
-        let other_nodes = Arc::new(build_addr_list(&my_address));
-        let socket = UdpSocket::bind(my_address.clone()).unwrap();
+/// Messages used by a node in raft mode, the rest belong to the bully mode
+fn used_by_raft(msg: &Message) -> bool {
+    matches!(
+        msg,
+        Message::Record(_)
+            | Message::Ack(_)
+            | Message::Nack(..)
+            | Message::Blockchain
+            | Message::SnapshotChunk(..)
+            | Message::End
+            | Message::Noop
+            | Message::RequestVote(_)
+            | Message::Vote(_)
+            | Message::AppendEntries(_)
+            | Message::AppendResponse(_)
+    )
+}
+
+impl Node {
+    pub fn new(
+        config: NodeConfig,
+        logger: Arc<Logger>,
+        blockchain_logger: Arc<Logger>,
+        store: Arc<BlockStore>,
+        snapshots: Arc<SnapshotStore>,
+        signing_key: Arc<SigningKey>,
+        genesis_config: Option<GenesisConfig>,
+    ) -> Self {
+        let my_address = config.bind.to_string();
+        let peers = config.peers.iter().map(|peer| peer.to_string()).collect();
+        let members = Arc::new(Membership::new(my_address.clone(), peers));
+        let socket = UdpSocket::bind(config.bind).unwrap();
+        let blockchain = restore_blockchain(&store, &snapshots, &logger, config.difficulty);
+        let leader_addr = Arc::new(RwLock::new(None));
+        let leader_condvar = Arc::new((Mutex::new(false), Condvar::new()));
+        let term = ElectionTerm::load(&format!("term_{}", config.node_name()))
+            .expect("Unable to load the election term");
+        let raft = match config.consensus {
+            ConsensusMode::Bully => None,
+            ConsensusMode::Raft => {
+                let raft_store = RaftStore::new(&format!("raft_{}", config.node_name()));
+                let raft = Raft::new(&config, raft_store, &blockchain, leader_addr.clone(), leader_condvar.clone(), logger.clone())
+                    .expect("Unable to load the raft state");
+                Some(Arc::new(Mutex::new(raft)))
+            }
+        };
 
         Node {
-            my_address: Arc::new(RwLock::new(my_address.clone())),
-            socket: Socket::new(socket),
-            leader_addr: Arc::new(RwLock::new(None)),
-            blockchain: Arc::new(RwLock::new(Blockchain::new())),
-            leader_condvar: Arc::new((Mutex::new(false), Condvar::new())),
+            my_address: Arc::new(RwLock::new(my_address)),
+            socket: Socket::new(socket, config.cluster_id, logger.clone()),
+            leader_addr,
+            blockchain: Arc::new(RwLock::new(blockchain)),
+            leader_condvar,
             lock_acquired: Arc::new((Mutex::new(false), Condvar::new())),
             mutex: Arc::new(Semaphore::new(1)),
             node_id_with_mutex: Arc::new(RwLock::new(None)),
             election_condvar: Arc::new((Mutex::new(None), Condvar::new())),
             alive: Arc::new(RwLock::new(true)),
             not_released_nodes: Arc::new(RwLock::new(0)),
-            msg_ack_cv: Arc::new((Mutex::new(false), Condvar::new())),
+            msg_ack_cv: Arc::new((Mutex::new(None), Condvar::new())),
             leader_down: Arc::new((Mutex::new(false), Condvar::new())),
+            pending_cv: Arc::new((Mutex::new(false), Condvar::new())),
             running_bully: Arc::new(Mutex::new(false)),
-            other_nodes,
+            term: Arc::new(term),
+            raft,
+            last_heartbeat: Arc::new(Mutex::new(Instant::now())),
+            heartbeat_interval: config.heartbeat_interval,
+            heartbeat_timeout: config.heartbeat_timeout,
+            members,
             logger,
             blockchain_logger,
+            store,
+            snapshots,
+            signing_key,
+            genesis_config,
             running_threads: vec![],
         }
     }
@@ -102,27 +259,76 @@ impl Node {
         self.logger.info(format!("Running node on: {} ", 
                         self.socket.local_addr().to_string()));
 
-        self.discover_leader();
-        self.detect_if_leader_is_down();
+        if self.raft.is_some() {
+            self.raft_timer();
+        } else {
+            self.discover_leader();
+            self.detect_if_leader_is_down();
+            self.heartbeat_monitor();
+        }
+        self.block_sealer();
         self.stdin_reader();
 
         while *self.alive.read().unwrap() {
             let (_, from, msg) = self.socket.recv_from();
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(err) => {
+                    self.logger.info(format!("Invalid message from {}: {}", from, err));
+                    continue;
+                }
+            };
+
+            if self.raft.is_some() && !used_by_raft(&msg) {
+                self.logger.info(format!("Message {:?} from {} ignored in raft mode", msg, from));
+                continue;
+            }
+
+            // Cualquier mensaje del lider muestra que sigue vivo
+            if *self.leader_addr.read().unwrap() == Some(from.to_string()) {
+                *self.last_heartbeat.lock().unwrap() = Instant::now();
+            }
+            // Y cualquier mensaje de un miembro, que no hay que sacarlo del cluster
+            self.members.seen(&from.to_string(), Instant::now());
 
-            match msg.as_str() {
-                ACQUIRE_MSG => self.handle_acquire_msg(from, self.mutex.clone(), self.node_id_with_mutex.clone()),
-                RELEASE_MSG => self.handle_release_msg(from),
-                LOCK_ACQUIRED => self.handle_lock_acquired(),
-                WHO_IS_LEADER => self.handle_who_is_leader(from),
-                COORDINATOR => self.handle_coordinator_msg(from),
-                BLOCKCHAIN => self.handle_blockchain_msg(),
-                OK => self.handle_ok_msg(from),
-                ELECTION => self.handle_election_msg(from),
-                ACK_MSG => self.handle_ack_msg(),
-                NOOP_MSG => {},
-                msg => self.handle_msg(msg, from),
+            match msg {
+                Message::Acquire => self.handle_acquire_msg(from, self.mutex.clone(), self.node_id_with_mutex.clone()),
+                Message::Release => self.handle_release_msg(from),
+                Message::LockAcquired => self.handle_lock_acquired(),
+                Message::WhoIsLeader => self.handle_who_is_leader(from),
+                Message::Coordinator(term) => self.handle_coordinator_msg(from, term),
+                Message::Blockchain => self.handle_blockchain_msg(from),
+                Message::Ok(term) => self.handle_ok_msg(from, term),
+                Message::Election(term) => self.handle_election_msg(from, term),
+                Message::Ack(hash) => self.handle_ack_msg(hash),
+                Message::Nack(hash, reason) => self.handle_nack_msg(hash, reason),
+                Message::Noop => {},
+                Message::Heartbeat => self.handle_heartbeat_msg(from),
+                Message::HeartbeatAck => {},
+                Message::End => {},
+                Message::SnapshotChunk(..) => {},
+                Message::Record(record) => self.handle_record_msg(*record, from),
+                Message::Block(block) => self.handle_block_msg(*block, from),
+                Message::Join => self.handle_join_msg(from),
+                Message::Leave => self.handle_leave_msg(from),
+                Message::Members(members) => self.handle_members_msg(members, from),
+                Message::RequestVote(request) => {
+                    self.raft_step(|raft, _| raft.handle_vote_request(from.to_string(), request, Instant::now()))
+                }
+                Message::Vote(response) => self.raft_step(|raft, blockchain| {
+                    raft.handle_vote(from.to_string(), response, Instant::now(), blockchain)
+                }),
+                Message::AppendEntries(append) => {
+                    self.raft_step(|raft, _| raft.handle_append_entries(from.to_string(), *append, Instant::now()))
+                }
+                Message::AppendResponse(response) => self.raft_step(|raft, blockchain| {
+                    raft.handle_append_response(from.to_string(), response, blockchain)
+                }),
             }
         }
+        if self.raft.is_none() {
+            self.announce_leave();
+        }
         self.finalize_running_threads();
     }
 
@@ -136,31 +342,185 @@ impl Node {
         }
     }
 
+    /// Runs a raft event and then appends the committed blocks, sends the messages
+    /// and the whole blockchain to the nodes that need it
+    fn raft_step<F>(&mut self, event: F)
+    where
+        F: FnOnce(&mut Raft, &Blockchain) -> Step,
+    {
+        let raft = match &self.raft {
+            Some(raft) => raft.clone(),
+            None => return,
+        };
+        let (messages, sync) = {
+            let mut raft = raft.lock().unwrap();
+            let step = event(&mut raft, &self.blockchain.read().unwrap());
+            // Se agregan con el lock de raft tomado asi se agregan en orden
+            apply_committed(step.committed, &self.blockchain, &self.store, &self.snapshots, &self.logger);
+            (step.messages, step.sync)
+        };
+        for (node, msg) in messages {
+            self.socket.send_to(msg, node);
+        }
+        for node in sync {
+            self.logger.info(format!("Sending the whole blockchain to {}", node));
+            self.send_blockchain(node);
+        }
+    }
+
     fn handle_who_is_leader(&mut self, from: SocketAddr) {
         if self.i_know_the_leader() {
             self.check_if_i_am_leader(from.to_string());
         }
     }
 
-    fn handle_blockchain_msg(&mut self) {
-        let mut blockchain = Blockchain::new();
+    /// Receives the whole blockchain from the leader, block by block,
+    /// or the last snapshot of the leader and only the blocks after it.
+    /// The received blockchain is validated (hashes, links and records,
+    /// and the state root of the snapshot) and only adopted if all the blocks are valid
+    /// and it extends the local blockchain
+    fn handle_blockchain_msg(&mut self, sender: SocketAddr) {
+        // Los bloques y partes que siguen llegan al loop principal, que los ignora
+        if *self.leader_addr.read().unwrap() != Some(sender.to_string()) {
+            self.logger.info(format!("Blockchain from {} ignored, it is not the leader", sender));
+            return;
+        }
+        let mut snapshot_chunks = SnapshotChunks::default();
+        let mut invalid_chunk = None;
+        let mut blocks = Vec::new();
         loop {
             let (_, from, msg) = self.socket.recv_from();
-            if msg == END {
-                break;
+            if from != sender {
+                self.logger.info(format!("Message from {} ignored while receiving the blockchain of {}", from, sender));
+                continue;
             }
-            let mut block = Block::new(blockchain.get_last_block_hash());
-            block.add_record(self.read_record(msg, from));
-            if let Err(err) = blockchain.append_block(block) {
-                println!("{}", err);
+            match msg {
+                Ok(Message::End) => break,
+                Ok(Message::SnapshotChunk(index, total, chunk)) => {
+                    if let Err(err) = snapshot_chunks.add(index, total, chunk) {
+                        invalid_chunk = Some(err);
+                    }
+                }
+                Ok(Message::Block(block)) => blocks.push(*block),
+                Ok(msg) => {
+                    self.logger.info(format!("Unexpected message {:?} from {} while receiving the blockchain", msg, from));
+                }
+                Err(err) => {
+                    self.logger.info(format!("Invalid message from {}: {}", from, err));
+                }
+            };
+        }
+
+        let (difficulty, genesis_hash) = {
+            let local = self.blockchain.read().unwrap();
+            (local.difficulty, local.get_genesis_hash())
+        };
+        let received = if let Some(err) = invalid_chunk {
+            Err(err)
+        } else if snapshot_chunks.is_empty() {
+            Blockchain::from_blocks(blocks, difficulty).map_err(|err| err.to_string())
+        } else {
+            snapshot_chunks
+                .assemble()
+                .and_then(|bytes| StateSnapshot::from_bytes(&bytes))
+                .and_then(|snapshot| {
+                    Blockchain::from_snapshot(snapshot, blocks, difficulty, genesis_hash).map_err(|err| err.to_string())
+                })
+        };
+        let blockchain = match received {
+            Ok(blockchain) => blockchain,
+            Err(err) => {
+                self.logger.info(format!("Received blockchain was rejected. {}", err));
+                return;
             }
+        };
+        if let Err(reason) = self.check_same_network(&blockchain).and_then(|_| self.check_extends_local(&blockchain)) {
+            self.logger.info(format!("Received blockchain was rejected. {}", reason));
+            return;
         }
+        if self.is_in_sync(&blockchain) {
+            self.logger.info("The local blockchain is already in sync with the leader".to_string());
+            return;
+        }
+        self.logger.info(format!(
+            "Received blockchain of {} blocks, {} of them replayed",
+            blockchain.len(),
+            blockchain.blocks.len()
+        ));
         if let Ok(mut blockchain_mut) = self.blockchain.write() {
+            // Primero el snapshot, asi al reiniciar los bloques guardados siguen al snapshot
+            if let Some(snapshot) = blockchain.take_snapshot() {
+                if let Err(err) = self.snapshots.save(&snapshot) {
+                    self.logger.info(format!("Unable to save the snapshot: {}", err));
+                }
+            }
+            if let Err(err) = self.store.rewrite(&blockchain.blocks) {
+                self.logger.info(format!("Unable to save the received blockchain: {}", err));
+            }
             *blockchain_mut = blockchain;
         }
+        if let Some(raft) = &self.raft {
+            raft.lock().unwrap().synced(&self.blockchain.read().unwrap());
+        }
+    }
+
+    /// Checks that a received blockchain belongs to the same network as this node:
+    /// it has to start with the same genesis block as the local blockchain
+    /// and with the configuration of the configuration file
+    fn check_same_network(&self, received: &Blockchain) -> Result<(), String> {
+        let local_genesis = self.blockchain.read().unwrap().get_genesis_hash();
+        if local_genesis.is_some() && local_genesis != received.get_genesis_hash() {
+            return Err("Its genesis block is different from the local one".to_string());
+        }
+        self.check_genesis_config(received.state.genesis.as_ref())
     }
 
-    fn handle_ok_msg(&mut self, from: SocketAddr) {
+    /// Checks that a received blockchain keeps every local block: the local blocks
+    /// are final (committed in raft mode), a shorter or different chain would undo them.
+    /// The last local block can't be compared if it's inside the received snapshot
+    fn check_extends_local(&self, received: &Blockchain) -> Result<(), String> {
+        let local = self.blockchain.read().unwrap();
+        if received.len() < local.len() {
+            return Err(format!("It has {} blocks, less than the {} local blocks", received.len(), local.len()));
+        }
+        let last_height = match local.len().checked_sub(1) {
+            Some(height) => height,
+            None => return Ok(()),
+        };
+        let received_hash = received.get_block_hash(last_height);
+        if received_hash.is_some() && received_hash != local.get_last_block_hash() {
+            return Err(format!("Its block {} is different from the local one", last_height));
+        }
+        Ok(())
+    }
+
+    /// Checks that a received genesis configuration is the one of the configuration
+    /// file, if this node has one. Every field is compared: the same chain id
+    /// with another qualification range or other admins is another network
+    fn check_genesis_config(&self, received: Option<&GenesisConfig>) -> Result<(), String> {
+        match (&self.genesis_config, received) {
+            (Some(config), Some(received)) if config != received => Err(format!(
+                "Its genesis configuration (chain {}) is different from the configuration file (chain {})",
+                received.chain_id, config.chain_id
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks if the local blockchain ends with the same block and the same
+    /// world state as the received one, comparing only the hashes
+    fn is_in_sync(&self, received: &Blockchain) -> bool {
+        let local = self.blockchain.read().unwrap();
+        local.get_last_block_hash() == received.get_last_block_hash()
+            && local.state.state_root() == received.state.state_root()
+    }
+
+    fn handle_ok_msg(&mut self, from: SocketAddr, term: u64) {
+        // El OK de una eleccion anterior no frena la eleccion actual
+        if !self.term.observe(term) {
+            self.logger.info(format!("Ok of {} with the old term {} ignored", from, term));
+            return;
+        }
         // Basicamente cada vez que recibamos un mensaje le hacemos un notify
         // a la otra convar y seteamos la IP del que recibimos.
         let (lock, cvar) = &*self.election_condvar;
@@ -168,59 +528,166 @@ impl Node {
         cvar.notify_all();
     }
 
-    fn handle_election_msg(&mut self, from: SocketAddr) {
+    fn handle_election_msg(&mut self, from: SocketAddr, term: u64) {
+        let current = self.term.observe(term);
+        // Si el term es viejo el OK le lleva el term actual al otro nodo
         self.socket
-            .send_to(OK.to_string(), from.to_string())
-            .unwrap();
+            .send_to(Message::Ok(self.term.current()), from.to_string());
+        // Una eleccion vieja no arranca una nueva, cambiaria al lider actual
+        if !current {
+            self.logger.info(format!("Election of {} with the old term {} ignored", from, term));
+            // El nodo viejo se entera de quien es el lider actual
+            if self.i_am_leader() {
+                self.socket
+                    .send_to(Message::Coordinator(self.term.current()), from.to_string());
+            }
+            return;
+        }
 
         let (lock, cvar) = &*self.leader_down;
         *lock.lock().unwrap() = true;
         cvar.notify_all();
     }
 
-    fn handle_ack_msg(&mut self) {
+    fn handle_ack_msg(&mut self, hash: BlockHash) {
+        self.notify_leader_response(hash, Ok(()));
+    }
+
+    /// The leader rejected the record sent by this node
+    fn handle_nack_msg(&mut self, hash: BlockHash, reason: String) {
+        self.notify_leader_response(hash, Err(reason));
+    }
+
+    /// Wakes up the stdin reader that is waiting for the answer of the leader
+    fn notify_leader_response(&mut self, hash: BlockHash, response: Result<(), String>) {
         let (lock, cv) = &*self.msg_ack_cv;
-        *lock.lock().unwrap() = true;
+        *lock.lock().unwrap() = Some((hash, response));
         cv.notify_all();
     }
 
-    /// Handler any msg from reading from stdin 
-    /// If Iam leader send ack msg to notify Iam up
-    fn handle_msg(&mut self, msg: &str, from: SocketAddr) {
-        let record = self.create_record(msg);
+    /// Handler for a record sent by a node that took the mutex.
+    /// The leader adds it to the pending records and answers with an ack,
+    /// the record will be sealed in a block by the `BlockSealer`.
+    /// If the record can't be executed the leader answers with a nack.
+    /// In raft mode the ack is sent when the block of the record is committed
+    fn handle_record_msg(&mut self, record: Record, from: SocketAddr) {
+        if !self.i_am_leader() {
+            self.logger.info(format!("Record from {} ignored, I am not the leader", from));
+            return;
+        }
 
-        if let Ok(mut blockchain_mut) = self.blockchain.write() {
-            let mut block = Block::new(blockchain_mut.get_last_block_hash());
-            block.add_record(record);
-            match blockchain_mut.append_block(block) {
-                Err(err) => println!("{}", err),
-                Ok(_) => {}
-            };
+        let hash = record.calculate_hash();
+        let submitted = match &self.raft {
+            Some(raft) => {
+                // Con el lock de raft tomado el bloque del registro no se puede
+                // comprometer antes de saber a quien avisarle
+                let mut raft = raft.lock().unwrap();
+                let submitted = self.blockchain.write().unwrap().submit_record(record);
+                if submitted.is_ok() {
+                    raft.track(hash, from.to_string());
+                }
+                submitted
+            }
+            None => match self.blockchain.write() {
+                Ok(mut blockchain_mut) => blockchain_mut.submit_record(record),
+                Err(_) => return,
+            },
+        };
+        if let Err(err) = submitted {
+            self.logger.info(format!("Record from {} rejected: {}", from, err));
+            self.socket
+                .send_to(Message::Nack(hash, err.to_string()), from.to_string());
+            return;
         }
 
-        if self.i_am_leader() {
+        let (lock, cv) = &*self.pending_cv;
+        *lock.lock().unwrap() = true;
+        cv.notify_all();
+
+        if self.raft.is_none() {
             self.socket
-                .send_to(ACK_MSG.to_string(), from.to_string())
-                .unwrap();
-            // Si el mensaje viene del leader, lo propago a todos
-            for node in &*self.other_nodes {
-                self.socket.send_to(msg.to_string(), node.clone()).unwrap();
-            }
+                .send_to(Message::Ack(hash), from.to_string());
         }
     }
 
+    /// Handler for a block sent by the leader, it's added to the local
+    /// copy only if its hash and its link with the last block are valid.
+    /// If the local state doesn't end with the state root of the block, or if
+    /// the node missed blocks (the block is ahead of the local blockchain or
+    /// doesn't follow its last block) the node asks the leader for the whole blockchain again
+    fn handle_block_msg(&mut self, block: Block, from: SocketAddr) {
+        if *self.leader_addr.read().unwrap() != Some(from.to_string()) {
+            self.logger.info(format!("Block from {} ignored, it is not the leader", from));
+            return;
+        }
+        if !block.verify_own_hash() {
+            self.logger.info(format!("Block from {} ignored, its hash is invalid", from));
+            return;
+        }
+        if let Err(reason) = self.check_genesis_config(block.genesis_config()) {
+            self.logger.info(format!("Block from {} rejected. {}", from, reason));
+            return;
+        }
+
+        let appended = match self.blockchain.write() {
+            Ok(mut blockchain_mut) => {
+                let appended = blockchain_mut.append_block(block.clone());
+                if appended.is_ok() {
+                    if let Err(err) = self.store.append(&block) {
+                        self.logger.info(format!("Unable to save block: {}", err));
+                    }
+                    match self.snapshots.save_if_due(&blockchain_mut) {
+                        Ok(Some(height)) => self.logger.info(format!("Snapshot of block {} saved", height)),
+                        Ok(None) => {}
+                        Err(err) => self.logger.info(format!("Unable to save snapshot: {}", err)),
+                    }
+                }
+                appended
+            }
+            Err(_) => return,
+        };
+        if let Err(err) = appended {
+            self.logger.info(format!("Block from {} rejected: {}", from, err));
+            // El estado local no es el del lider o se perdio algun bloque,
+            // se le pide la blockchain de nuevo
+            let out_of_sync = match err {
+                BlockchainError::InvalidStateRoot => {
+                    self.logger.info(format!("The local state diverged from the leader at block {}", block.header.height));
+                    true
+                }
+                BlockchainError::InvalidHeight(expected, got) if got > expected => {
+                    self.logger.info(format!("Blocks {} to {} from the leader were missed", expected, got - 1));
+                    true
+                }
+                BlockchainError::BrokenLink => {
+                    self.logger.info(format!("Block {} doesn't follow the last local block", block.header.height));
+                    true
+                }
+                _ => false,
+            };
+            if out_of_sync {
+                self.socket
+                    .send_to(Message::WhoIsLeader, from.to_string());
+            }
+        }
+    }
     /// Spawn thread for read from stdin
     fn stdin_reader(&mut self) {
+        let leader = LeaderHandles {
+            leader_condvar: self.leader_condvar.clone(),
+            leader_addr: self.leader_addr.clone(),
+            msg_ack_cv: self.msg_ack_cv.clone(),
+            leader_down_cv: self.leader_down.clone(),
+            lock_acquired: self.lock_acquired.clone(),
+        };
         let mut reader = StdinReader::new(
-            self.leader_condvar.clone(),
+            leader,
             self.socket.try_clone(),
-            self.leader_addr.clone(),
             self.alive.clone(),
-            self.msg_ack_cv.clone(),
-            self.leader_down.clone(),
-            self.lock_acquired.clone(),
             self.blockchain.clone(),
-            self.blockchain_logger.clone()
+            self.blockchain_logger.clone(),
+            self.signing_key.clone(),
+            self.raft.is_none(),
         );
 
         self.running_threads.push(Some(thread::spawn(move || {
@@ -228,6 +695,64 @@ impl Node {
         })));
     }
 
+    /// Spawn thread that seals the pending records in blocks
+    fn block_sealer(&mut self) {
+        let mut block_sealer = BlockSealer::new(
+            self.blockchain.clone(),
+            self.store.clone(),
+            self.snapshots.clone(),
+            self.socket.try_clone(),
+            self.my_address.clone(),
+            self.leader_addr.clone(),
+            self.members.clone(),
+            self.pending_cv.clone(),
+            self.logger.clone(),
+            self.alive.clone(),
+            self.signing_key.clone(),
+            self.genesis_config.clone(),
+            self.raft.clone(),
+        );
+
+        self.running_threads.push(Some(thread::spawn(move || {
+            block_sealer.run();
+        })));
+    }
+
+    /// Spawn thread with the timeouts of raft (elections and heartbeats)
+    fn raft_timer(&mut self) {
+        let mut raft_timer = RaftTimer::new(
+            self.raft.clone().unwrap(),
+            self.blockchain.clone(),
+            self.socket.try_clone(),
+            self.alive.clone(),
+        );
+
+        self.running_threads.push(Some(thread::spawn(move || {
+            raft_timer.run();
+        })));
+    }
+
+    /// Spawn thread that sends the heartbeats of the leader
+    /// and starts an election when the leader stops sending them
+    fn heartbeat_monitor(&mut self) {
+        let mut heartbeat_monitor = HeartbeatMonitor::new(
+            self.my_address.clone(),
+            self.leader_addr.clone(),
+            self.socket.try_clone(),
+            self.members.clone(),
+            self.last_heartbeat.clone(),
+            self.leader_down.clone(),
+            self.heartbeat_interval,
+            self.heartbeat_timeout,
+            self.logger.clone(),
+            self.alive.clone(),
+        );
+
+        self.running_threads.push(Some(thread::spawn(move || {
+            heartbeat_monitor.run();
+        })));
+    }
+
     /// Spawn Thread to check which is the addr of the leader
     fn discover_leader(&mut self) -> () {
         let mut leader_discoverer = LeaderDiscoverer::new(
@@ -235,7 +760,8 @@ impl Node {
             self.leader_addr.clone(),
             self.my_address.clone(),
             self.socket.try_clone(),
-            self.other_nodes.clone(),
+            self.members.clone(),
+            self.term.clone(),
             self.logger.clone()
         );
 
@@ -249,6 +775,8 @@ impl Node {
         let mut leader_down_handler = LeaderDownHandler::new(
             self.my_address.clone(),
             self.socket.try_clone(),
+            self.members.clone(),
+            self.term.clone(),
             self.election_condvar.clone(),
             self.leader_down.clone(),
             self.running_bully.clone(),
@@ -291,24 +819,128 @@ impl Node {
 
     /// When a node recv a coordinator msg then has to set
     /// Addr from that msg to the leader addr
-    fn handle_coordinator_msg(&mut self, leader: SocketAddr) -> () {
+    fn handle_coordinator_msg(&mut self, leader: SocketAddr, term: u64) {
+        // Un coordinador de una eleccion vieja (o de un nodo que estuvo caido)
+        // no reemplaza al lider actual
+        if !self.term.observe(term) {
+            self.logger.info(format!(
+                "Coordinator {} with the old term {} ignored (current term {})",
+                leader, term, self.term.current()
+            ));
+            // El nodo viejo se entera de quien es el lider actual
+            if self.i_am_leader() {
+                self.socket
+                    .send_to(Message::Coordinator(self.term.current()), leader.to_string());
+            }
+            return;
+        }
         let (lock, cvar) = &*self.leader_condvar;
         *lock.lock().unwrap() = true;
         cvar.notify_all();
 
+        let mut old_leader = None;
         if let Ok(mut leader_addr_mut) = self.leader_addr.write() {
-            *leader_addr_mut = Some(leader.to_string());
+            old_leader = leader_addr_mut.replace(leader.to_string());
         }
         let mut leader_addr = (*self.leader_addr.read().unwrap()).clone();
+        *self.last_heartbeat.lock().unwrap() = Instant::now();
 
         self.logger.info(format!("New leader found in address: {}", 
                         leader_addr.get_or_insert("??".to_string())));
 
+        // El lider anterior se cayo, el nuevo lider no lo va a volver a enviar
+        if let Some(old_leader) = old_leader {
+            if old_leader != leader.to_string() {
+                self.members.remove(&old_leader);
+            }
+        }
+        if !self.i_am_leader() {
+            self.socket
+                .send_to(Message::Join, leader.to_string());
+        }
+
         let (lock, _) = &*self.leader_down;
         *lock.lock().unwrap() = false;
         *self.running_bully.lock().unwrap() = false;
     }
 
+    /// A node that suspects the leader is down asks if it's alive, the leader answers
+    /// with a coordinator (not with the blockchain, like a new node).
+    /// A coordinator and not a heartbeat so two nodes that think they are the leader
+    /// don't answer each other forever, one of them follows the other.
+    /// A follower answers the heartbeats of the leader so it stays a member
+    fn handle_heartbeat_msg(&mut self, from: SocketAddr) {
+        if from.to_string() == *self.my_address.read().unwrap() {
+            return;
+        }
+        if self.i_am_leader() {
+            self.socket
+                .send_to(Message::Coordinator(self.term.current()), from.to_string());
+        } else if *self.leader_addr.read().unwrap() == Some(from.to_string()) {
+            self.socket
+                .send_to(Message::HeartbeatAck, from.to_string());
+        }
+    }
+
+    /// A node joined the cluster, the leader adds it and sends the new list to every node.
+    /// If it was already a member only that node gets the list
+    fn handle_join_msg(&mut self, from: SocketAddr) {
+        if !self.i_am_leader() {
+            return;
+        }
+        if self.members.add(from.to_string()) {
+            self.logger.info(format!("Node {} joined the cluster", from));
+            self.broadcast_members();
+        } else {
+            self.socket
+                .send_to(Message::Members(self.members.with_me()), from.to_string());
+        }
+    }
+
+    /// A node left the cluster. If it was the leader a new one is elected
+    fn handle_leave_msg(&mut self, from: SocketAddr) {
+        if self.members.remove(&from.to_string()) {
+            self.logger.info(format!("Node {} left the cluster", from));
+        }
+        if self.i_am_leader() {
+            self.broadcast_members();
+        } else if *self.leader_addr.read().unwrap() == Some(from.to_string()) {
+            *self.leader_addr.write().unwrap() = None;
+            let (lock, cvar) = &*self.leader_down;
+            *lock.lock().unwrap() = true;
+            cvar.notify_all();
+        }
+    }
+
+    /// The leader sent every node of the cluster
+    fn handle_members_msg(&mut self, members: Vec<String>, from: SocketAddr) {
+        if *self.leader_addr.read().unwrap() == Some(from.to_string()) {
+            self.members.replace(members);
+        }
+    }
+
+    /// Sends the list of members to every node, only the leader does it
+    fn broadcast_members(&mut self) {
+        let members = self.members.with_me();
+        for node in self.members.members() {
+            self.socket
+                .send_to(Message::Members(members.clone()), node);
+        }
+    }
+
+    /// Before closing the node tells the leader it leaves the cluster,
+    /// or every node if it's the leader so they elect a new one
+    fn announce_leave(&mut self) {
+        let nodes = if self.i_am_leader() {
+            self.members.members()
+        } else {
+            (*self.leader_addr.read().unwrap()).clone().into_iter().collect()
+        };
+        for node in nodes {
+            self.socket.send_to(Message::Leave, node);
+        }
+    }
+
     fn handle_acquire_msg(
         &mut self,
         node: SocketAddr,
@@ -330,8 +962,7 @@ impl Node {
                 *node_id = Some(node);
             }
             socket_clone
-                .send_to(LOCK_ACQUIRED.to_string(), node.to_string())
-                .unwrap();
+                .send_to(Message::LockAcquired, node.to_string());
 
 
             // Si paso un timeout sin recibir RELEASE, hago un release
@@ -353,7 +984,7 @@ impl Node {
     ///  Notify all node that the mutex was realese after begin adquired
     fn handle_release_msg(&mut self, node: SocketAddr) {
         // Only if the node that had the mutex sent the release
-        if node != (*self.node_id_with_mutex.read().unwrap()).unwrap() {
+        if Some(node) != *self.node_id_with_mutex.read().unwrap() {
             return;
         }
 
@@ -376,67 +1007,48 @@ impl Node {
     fn check_if_i_am_leader(&mut self, node_that_asked: String) -> () {
         if self.i_am_leader() {
             self.socket
-                .send_to(COORDINATOR.to_string(), node_that_asked.clone())
-                .unwrap();
+                .send_to(Message::Coordinator(self.term.current()), node_that_asked.clone());
             self.send_blockchain(node_that_asked.clone());
         }
     }
 
-    /// Send complete blockchain when a new node is conected
+    /// Send complete blockchain when a new node is conected.
+    /// If there is a snapshot only the snapshot and the blocks after it are sent
     fn send_blockchain(&mut self, from: String) {
         self.socket
-            .send_to(BLOCKCHAIN.to_string(), from.clone())
-            .unwrap();
+            .send_to(Message::Blockchain, from.clone());
 
+        let snapshot = match self.snapshots.load() {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                self.logger.info(format!("Unable to read the snapshot: {}", err));
+                None
+            }
+        };
         if let Ok(blockchain_mut) = self.blockchain.read() {
-            for b in blockchain_mut.get_blocks() {
-                let mut data_to_send = String::new();
-                match &b.records[0].record {
-                    RecordData::CreateStudent(id, qualification) => {
-                        data_to_send.push_str(
-                            &(format!(
-                                "{},{},{},{}",
-                                &id,
-                                &(qualification.to_string()),
-                                &b.records[0].created_at.as_millis().to_string(),
-                                &b.records[0].from
-                            )),
-                        );
+            // El snapshot se usa solo si es de un bloque de esta blockchain
+            let snapshot = snapshot.and_then(|snapshot| {
+                let blocks = blockchain_mut.get_blocks_after(&snapshot)?;
+                Some((snapshot, blocks))
+            });
+            let blocks = match snapshot {
+                Some((snapshot, blocks)) => {
+                    let bytes = snapshot.to_bytes();
+                    let chunks: Vec<&[u8]> = bytes.chunks(SNAPSHOT_CHUNK_SIZE).collect();
+                    let total = chunks.len() as u32;
+                    for (index, chunk) in chunks.into_iter().enumerate() {
+                        let msg = Message::SnapshotChunk(index as u32, total, chunk.to_vec());
+                        self.socket.send_to(msg, from.clone());
                     }
-                };
+                    blocks
+                }
+                None => blockchain_mut.get_blocks(),
+            };
+            for b in blocks {
                 self.socket
-                    .send_to(data_to_send, from.clone().to_string())
-                    .unwrap();
+                    .send_to(Message::Block(Box::new(b)), from.clone());
             }
         }
-        self.socket.send_to(END.to_string(), from).unwrap();
-    }
-
-    /// Read record info from addrs 
-    fn read_record(&self, msg: String, from: SocketAddr) -> Record {
-        let student_data: Vec<&str> = msg.split(",").collect();
-        let record = Record::new(
-            from.to_string().into(),
-            RecordData::CreateStudent(
-                student_data[0].into(),
-                student_data[1].parse::<i32>().unwrap(),
-            ),
-            Duration::from_millis(student_data[2].parse::<u64>().unwrap()),
-        );
-        record
-    }
-
-    /// Create record from msg to be save in blockchain 
-    fn create_record(&self, msg: &str) -> Record {
-        let student_data: Vec<&str> = msg.split(",").collect();
-        let record = Record::new(
-            student_data[3].to_string(),
-            RecordData::CreateStudent(
-                student_data[0].into(),
-                student_data[1].parse::<i32>().unwrap(),
-            ),
-            Duration::from_millis(student_data[2].parse::<u64>().unwrap()),
-        );
-        record
+        self.socket.send_to(Message::End, from);
     }
 }
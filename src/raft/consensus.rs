@@ -0,0 +1,789 @@
+use super::messages::{AppendEntries, AppendResponse, LogEntry, VoteRequest, VoteResponse};
+use super::store::{PersistentState, RaftStore};
+use crate::blockchain::block::Block;
+use crate::blockchain::block_store::BlockStore;
+use crate::blockchain::blockchain::Blockchain;
+use crate::blockchain::hash::BlockHash;
+use crate::blockchain::record::Record;
+use crate::blockchain::snapshot::SnapshotStore;
+use crate::config::NodeConfig;
+use crate::utils::logger::Logger;
+use crate::utils::messages::Message;
+
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::io::Error;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// Max amount of entries sent in one `AppendEntries` (it has to fit in a datagram)
+const MAX_ENTRIES_PER_APPEND: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+/// What a node has to do after a raft event
+#[derive(Debug, Default)]
+pub struct Step {
+    /// Messages to send, with the address of the node
+    pub messages: Vec<(String, Message)>,
+
+    /// Blocks that were committed, they have to be appended to the blockchain in order
+    pub committed: Vec<Block>,
+
+    /// Nodes that need the whole blockchain, the leader no longer has the blocks they miss
+    pub sync: Vec<String>,
+}
+
+/// Consensus of the raft mode. The log is the blockchain: the entry `i`
+/// is the block of height `i`, the committed entries are the blocks
+/// of the blockchain and the entries that aren't committed yet are kept here.
+/// A block is only committed (and appended by every node) once a majority
+/// of the nodes stored it, so a block that was appended is never lost
+/// when the leader changes. The leader only proposes a block after the
+/// previous one was committed, so there is at most one entry not committed
+pub struct Raft {
+    my_address: String,
+    peers: Vec<String>,
+    pub role: Role,
+    state: PersistentState,
+
+    /// Amount of committed entries, the length of the blockchain
+    commit_len: u64,
+
+    /// Nodes that voted for this node in the current term
+    votes: HashSet<String>,
+
+    /// Leader: length of the log of each node from which to send the entries
+    next_len: HashMap<String, u64>,
+
+    /// Leader: length of the log of each node known to match the leader
+    match_len: HashMap<String, u64>,
+
+    /// Who sent each record (by hash) that isn't committed yet. They are told
+    /// that the record was accepted only when its block is committed,
+    /// and that it was rejected if its block is discarded
+    submitters: HashMap<BlockHash, String>,
+
+    election_deadline: Instant,
+    next_heartbeat: Instant,
+    heartbeat_interval: Duration,
+    election_timeout: Duration,
+    store: RaftStore,
+    leader_addr: Arc<RwLock<Option<String>>>,
+    leader_condvar: Arc<(Mutex<bool>, Condvar)>,
+    logger: Arc<Logger>,
+}
+
+impl Raft {
+    /// Starts as a follower with the state saved in the store.
+    /// The entries that were committed before a crash (they are already
+    /// in the blockchain) are moved out of the log
+    pub fn new(
+        config: &NodeConfig,
+        store: RaftStore,
+        blockchain: &Blockchain,
+        leader_addr: Arc<RwLock<Option<String>>>,
+        leader_condvar: Arc<(Mutex<bool>, Condvar)>,
+        logger: Arc<Logger>,
+    ) -> Result<Self, Error> {
+        let state = store.load()?;
+        let now = Instant::now();
+        let mut raft = Raft {
+            my_address: config.bind.to_string(),
+            peers: config.peers.iter().map(|peer| peer.to_string()).collect(),
+            role: Role::Follower,
+            commit_len: state.entries_start,
+            state,
+            votes: HashSet::new(),
+            next_len: HashMap::new(),
+            match_len: HashMap::new(),
+            submitters: HashMap::new(),
+            election_deadline: now,
+            next_heartbeat: now,
+            heartbeat_interval: config.heartbeat_interval,
+            election_timeout: config.heartbeat_timeout,
+            store,
+            leader_addr,
+            leader_condvar,
+            logger,
+        };
+        let len = blockchain.len() as u64;
+        if raft.commit_len > len {
+            // El store perdio bloques, las entradas no siguen a la blockchain
+            raft.state.entries.clear();
+        }
+        raft.commit_to(len, &mut Step::default());
+        // Los bloques que no pasaron por el log (de antes del modo raft) tienen term 0
+        raft.commit_len = len;
+        raft.persist();
+        raft.reset_election_deadline(now);
+        Ok(raft)
+    }
+
+    /// Remembers who sent a record accepted by the leader,
+    /// it gets the `ACK` when the block of the record is committed
+    pub fn track(&mut self, record: BlockHash, submitter: String) {
+        self.submitters.insert(record, submitter);
+    }
+
+    /// True while a proposed block is waiting to be committed
+    pub fn has_uncommitted(&self) -> bool {
+        !self.state.entries.is_empty()
+    }
+
+    /// Starts an election if the leader didn't send anything before the timeout,
+    /// or sends the heartbeats if this node is the leader
+    pub fn tick(&mut self, now: Instant, blockchain: &Blockchain) -> Step {
+        let mut step = Step::default();
+        match self.role {
+            Role::Leader if now >= self.next_heartbeat => {
+                self.next_heartbeat = now + self.heartbeat_interval;
+                for peer in self.peers.clone() {
+                    step.messages.push((peer.clone(), self.append_for(&peer, blockchain)));
+                }
+            }
+            Role::Leader => {}
+            _ if now >= self.election_deadline => return self.start_election(now, blockchain),
+            _ => {}
+        }
+        step
+    }
+
+    /// Adds a block sealed by the leader to the log and sends it to every node.
+    /// If this node is not the leader anymore the block is discarded
+    pub fn propose(&mut self, block: Block, blockchain: &Blockchain) -> Step {
+        let mut step = Step::default();
+        if self.role != Role::Leader {
+            self.reject(&block.records, "The leader lost its leadership before the record was committed", &mut step);
+            return step;
+        }
+        self.state.entries.push(LogEntry {
+            term: self.state.current_term,
+            block,
+        });
+        self.persist();
+        for peer in self.peers.clone() {
+            step.messages.push((peer.clone(), self.append_for(&peer, blockchain)));
+        }
+        self.advance_commit(&mut step);
+        step
+    }
+
+    pub fn handle_vote_request(&mut self, from: String, request: VoteRequest, now: Instant) -> Step {
+        if request.term > self.state.current_term {
+            self.step_down(request.term);
+        }
+        // Solo se vota a un candidato que tenga todas las entradas de este nodo
+        let up_to_date = (request.last_term, request.last_len) >= (self.last_term(), self.last_len());
+        let granted = request.term == self.state.current_term
+            && up_to_date
+            && self.state.voted_for.as_ref().is_none_or(|candidate| *candidate == from);
+        if granted {
+            self.state.voted_for = Some(from.clone());
+            self.persist();
+            self.reset_election_deadline(now);
+        }
+        let response = VoteResponse {
+            term: self.state.current_term,
+            granted,
+        };
+        Step {
+            messages: vec![(from, Message::Vote(response))],
+            ..Step::default()
+        }
+    }
+
+    pub fn handle_vote(&mut self, from: String, response: VoteResponse, now: Instant, blockchain: &Blockchain) -> Step {
+        if response.term > self.state.current_term {
+            self.step_down(response.term);
+            return Step::default();
+        }
+        if self.role != Role::Candidate || response.term != self.state.current_term || !response.granted {
+            return Step::default();
+        }
+        self.votes.insert(from);
+        if self.is_majority(self.votes.len() + 1) {
+            return self.become_leader(now, blockchain);
+        }
+        Step::default()
+    }
+
+    /// Appends the entries of the leader if the log matches the leader
+    /// before them, the entries that conflict with the leader are removed.
+    /// Then commits the entries the leader already committed
+    pub fn handle_append_entries(&mut self, from: String, append: AppendEntries, now: Instant) -> Step {
+        let mut step = Step::default();
+        if append.term < self.state.current_term {
+            let response = self.append_response(false, self.last_len());
+            step.messages.push((from, response));
+            return step;
+        }
+        if append.term > self.state.current_term || self.role != Role::Follower {
+            self.step_down(append.term);
+        }
+        self.set_leader(Some(from.clone()));
+        self.reset_election_deadline(now);
+
+        if append.prev_len > self.last_len() {
+            let response = self.append_response(false, self.last_len());
+            step.messages.push((from, response));
+            return step;
+        }
+        // Las entradas comprometidas coinciden con las del lider
+        if append.prev_len > self.commit_len && self.term_at(append.prev_len) != append.prev_term {
+            let conflict = (append.prev_len - 1 - self.commit_len) as usize;
+            self.truncate_entries(conflict, &mut step);
+            self.persist();
+            let response = self.append_response(false, self.commit_len);
+            step.messages.push((from, response));
+            return step;
+        }
+
+        let match_len = append.prev_len + append.entries.len() as u64;
+        let mut changed = false;
+        for (i, entry) in append.entries.into_iter().enumerate() {
+            let index = append.prev_len + i as u64;
+            if index < self.commit_len {
+                continue;
+            }
+            let position = (index - self.commit_len) as usize;
+            if let Some(own) = self.state.entries.get(position) {
+                if own.term == entry.term {
+                    continue;
+                }
+                self.truncate_entries(position, &mut step);
+            }
+            self.state.entries.push(entry);
+            changed = true;
+        }
+        if changed {
+            self.persist();
+        }
+
+        if append.leader_commit > self.commit_len {
+            self.commit_to(append.leader_commit.min(match_len), &mut step);
+        }
+        let response = self.append_response(true, match_len);
+        step.messages.push((from, response));
+        step
+    }
+
+    pub fn handle_append_response(
+        &mut self,
+        from: String,
+        response: AppendResponse,
+        blockchain: &Blockchain,
+    ) -> Step {
+        let mut step = Step::default();
+        if response.term > self.state.current_term {
+            self.step_down(response.term);
+            return step;
+        }
+        if self.role != Role::Leader || response.term != self.state.current_term {
+            return step;
+        }
+
+        if response.success {
+            let matched = self.match_len.entry(from.clone()).or_insert(0);
+            *matched = (*matched).max(response.match_len);
+            self.next_len.insert(from.clone(), response.match_len);
+            self.advance_commit(&mut step);
+            if response.match_len < self.last_len() {
+                step.messages.push((from.clone(), self.append_for(&from, blockchain)));
+            }
+        } else if response.match_len < blockchain.first_height() {
+            // El lider arranco de un snapshot y no tiene esos bloques
+            self.next_len.insert(from.clone(), self.commit_len);
+            step.sync.push(from);
+        } else {
+            self.next_len.insert(from.clone(), response.match_len.min(self.last_len()));
+            step.messages.push((from.clone(), self.append_for(&from, blockchain)));
+        }
+        step
+    }
+
+    /// The node received the whole blockchain from the leader, every block is committed.
+    /// The terms of the received blocks are unknown, they are kept as 0: with a newer
+    /// term this node could get the vote of a node that has entries it doesn't have.
+    /// A blockchain that isn't longer than the committed entries changes nothing,
+    /// committed entries are never undone
+    pub fn synced(&mut self, blockchain: &Blockchain) {
+        let len = blockchain.len() as u64;
+        if len <= self.commit_len {
+            return;
+        }
+        self.commit_len = len;
+        self.state.entries.clear();
+        self.state.term_starts.clear();
+        self.persist();
+    }
+
+    fn start_election(&mut self, now: Instant, blockchain: &Blockchain) -> Step {
+        self.state.current_term += 1;
+        self.state.voted_for = Some(self.my_address.clone());
+        self.role = Role::Candidate;
+        self.votes.clear();
+        self.persist();
+        self.set_leader(None);
+        self.reset_election_deadline(now);
+        self.logger.info(format!("Election of term {} started", self.state.current_term));
+
+        if self.is_majority(1) {
+            return self.become_leader(now, blockchain);
+        }
+        let request = VoteRequest {
+            term: self.state.current_term,
+            last_len: self.last_len(),
+            last_term: self.last_term(),
+        };
+        Step {
+            messages: self
+                .peers
+                .iter()
+                .map(|peer| (peer.clone(), Message::RequestVote(request.clone())))
+                .collect(),
+            ..Step::default()
+        }
+    }
+
+    /// The entries of previous terms are proposed again in the current term,
+    /// they are the same blocks so the nodes that already have them keep them.
+    /// This way they are committed by counting the nodes that store them
+    fn become_leader(&mut self, now: Instant, blockchain: &Blockchain) -> Step {
+        self.role = Role::Leader;
+        self.set_leader(Some(self.my_address.clone()));
+        self.logger.info(format!("Elected leader of term {}", self.state.current_term));
+
+        if self.has_uncommitted() {
+            let term = self.state.current_term;
+            self.state.entries.iter_mut().for_each(|entry| entry.term = term);
+            self.persist();
+        }
+        self.next_len = self.peers.iter().map(|peer| (peer.clone(), self.last_len())).collect();
+        self.match_len.clear();
+        self.next_heartbeat = now + self.heartbeat_interval;
+
+        let mut step = Step::default();
+        for peer in self.peers.clone() {
+            step.messages.push((peer.clone(), self.append_for(&peer, blockchain)));
+        }
+        self.advance_commit(&mut step);
+        step
+    }
+
+    fn step_down(&mut self, term: u64) {
+        if term > self.state.current_term {
+            self.state.current_term = term;
+            self.state.voted_for = None;
+            self.persist();
+        }
+        if self.role != Role::Follower {
+            self.logger.info(format!("Back to follower in term {}", self.state.current_term));
+        }
+        if self.role == Role::Leader {
+            self.set_leader(None);
+        }
+        self.role = Role::Follower;
+    }
+
+    /// Entries the node doesn't have yet, from the blockchain if they are committed
+    fn append_for(&self, peer: &str, blockchain: &Blockchain) -> Message {
+        let next_len = self
+            .next_len
+            .get(peer)
+            .copied()
+            .unwrap_or_else(|| self.last_len())
+            .max(blockchain.first_height())
+            .min(self.last_len());
+        let mut entries = Vec::new();
+        for index in next_len..self.last_len() {
+            if entries.len() == MAX_ENTRIES_PER_APPEND {
+                break;
+            }
+            let entry = if index < self.commit_len {
+                match blockchain.get_block(index as usize) {
+                    Some(block) => LogEntry {
+                        term: self.committed_term(index),
+                        block: block.clone(),
+                    },
+                    None => break,
+                }
+            } else {
+                self.state.entries[(index - self.commit_len) as usize].clone()
+            };
+            entries.push(entry);
+        }
+        Message::AppendEntries(Box::new(AppendEntries {
+            term: self.state.current_term,
+            prev_len: next_len,
+            prev_term: self.term_at(next_len),
+            entries,
+            leader_commit: self.commit_len,
+        }))
+    }
+
+    fn append_response(&self, success: bool, match_len: u64) -> Message {
+        Message::AppendResponse(AppendResponse {
+            term: self.state.current_term,
+            success,
+            match_len,
+        })
+    }
+
+    /// Commits the longest log of the current term stored by a majority of the nodes
+    fn advance_commit(&mut self, step: &mut Step) {
+        for len in (self.commit_len + 1..=self.last_len()).rev() {
+            if self.term_at(len) != self.state.current_term {
+                break;
+            }
+            let stored = 1 + self.match_len.values().filter(|&&matched| matched >= len).count();
+            if self.is_majority(stored) {
+                self.commit_to(len, step);
+                return;
+            }
+        }
+    }
+
+    /// Moves the entries before `len` out of the log, the caller appends their blocks
+    /// (added to `step`) and then sends the `ACK` of their records.
+    /// The state is saved with the next change: if the node crashes before that the
+    /// entries are moved again when it restarts, they will already be in the blockchain
+    fn commit_to(&mut self, len: u64, step: &mut Step) {
+        while self.commit_len < len && !self.state.entries.is_empty() {
+            let entry = self.state.entries.remove(0);
+            if self.committed_term(self.commit_len) != entry.term {
+                self.state.term_starts.push((self.commit_len, entry.term));
+            }
+            self.commit_len += 1;
+            for record in entry.block.records.iter() {
+                let hash = record.calculate_hash();
+                if let Some(submitter) = self.submitters.remove(&hash) {
+                    step.messages.push((submitter, Message::Ack(hash)));
+                }
+            }
+            step.committed.push(entry.block);
+        }
+        self.state.entries_start = self.commit_len;
+    }
+
+    /// Removes the entries from `len` on, they conflict with the entries of the leader
+    fn truncate_entries(&mut self, len: usize, step: &mut Step) {
+        let removed: Vec<LogEntry> = self.state.entries.drain(len.min(self.state.entries.len())..).collect();
+        for entry in removed {
+            self.reject(&entry.block.records, "The block of the record was replaced by the one of a new leader", step);
+        }
+    }
+
+    /// Tells the submitters of the records that they were not added
+    fn reject(&mut self, records: &[Record], reason: &str, step: &mut Step) {
+        for record in records {
+            let hash = record.calculate_hash();
+            if let Some(submitter) = self.submitters.remove(&hash) {
+                step.messages.push((submitter, Message::Nack(hash, reason.to_string())));
+            }
+        }
+    }
+
+    fn is_majority(&self, count: usize) -> bool {
+        count * 2 > self.peers.len() + 1
+    }
+
+    fn last_len(&self) -> u64 {
+        self.commit_len + self.state.entries.len() as u64
+    }
+
+    fn last_term(&self) -> u64 {
+        self.term_at(self.last_len())
+    }
+
+    /// Term of the last entry of the log of length `len`
+    fn term_at(&self, len: u64) -> u64 {
+        if len == 0 {
+            0
+        } else if len > self.commit_len {
+            self.state.entries[(len - 1 - self.commit_len) as usize].term
+        } else {
+            self.committed_term(len - 1)
+        }
+    }
+
+    fn committed_term(&self, index: u64) -> u64 {
+        self.state
+            .term_starts
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= index)
+            .map_or(0, |(_, term)| *term)
+    }
+
+    fn persist(&mut self) {
+        self.state.entries_start = self.commit_len;
+        // Votar o confirmar entradas sin guardarlas rompe las garantias de raft
+        self.store.save(&self.state).expect("Unable to save the raft state");
+    }
+
+    fn reset_election_deadline(&mut self, now: Instant) {
+        let timeout = self.election_timeout.as_millis() as u64;
+        let jitter = rand::thread_rng().gen_range(0..timeout.max(1));
+        self.election_deadline = now + Duration::from_millis(timeout + jitter);
+    }
+
+    fn set_leader(&mut self, leader: Option<String>) {
+        let mut leader_addr = self.leader_addr.write().unwrap();
+        if *leader_addr == leader {
+            return;
+        }
+        if let Some(leader) = &leader {
+            self.logger.info(format!("Leader of term {} is {}", self.state.current_term, leader));
+            let (lock, cvar) = &*self.leader_condvar;
+            *lock.lock().unwrap() = true;
+            cvar.notify_all();
+        }
+        *leader_addr = leader;
+    }
+}
+
+/// Appends the committed blocks to the blockchain and saves them.
+/// The records that were submitted while the block was waiting to be committed
+/// were checked without its records, the ones that can't be executed anymore are dropped.
+/// Panics if a committed block is rejected: raft already counts it as part of the
+/// blockchain, and without it the node can't send or append the blocks after it
+pub fn apply_committed(
+    blocks: Vec<Block>,
+    blockchain: &RwLock<Blockchain>,
+    store: &BlockStore,
+    snapshots: &SnapshotStore,
+    logger: &Logger,
+) {
+    if blocks.is_empty() {
+        return;
+    }
+    let mut blockchain = blockchain.write().unwrap();
+    for block in blocks {
+        let height = block.header.height;
+        if let Err(err) = blockchain.append_block(block.clone()) {
+            logger.info(format!("Committed block {} was rejected: {}", height, err));
+            panic!("Committed block {} was rejected: {}", height, err);
+        }
+        if let Err(err) = store.append(&block) {
+            logger.info(format!("Unable to save block: {}", err));
+        }
+        match snapshots.save_if_due(&blockchain) {
+            Ok(Some(height)) => logger.info(format!("Snapshot of block {} saved", height)),
+            Ok(None) => {}
+            Err(err) => logger.info(format!("Unable to save snapshot: {}", err)),
+        }
+        logger.info(format!("Block {} committed", height));
+    }
+    let dropped = blockchain.drop_invalid_pending();
+    if dropped > 0 {
+        logger.info(format!("{} pending records can't be executed anymore and were dropped", dropped));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::genesis::GenesisConfig;
+    use crate::blockchain::record::{Record, RecordData};
+    use ed25519_dalek::SigningKey;
+    use std::collections::VecDeque;
+
+    const NODES: [&str; 3] = ["127.0.0.1:9701", "127.0.0.1:9702", "127.0.0.1:9703"];
+
+    /// Node that sends the records to the leader, it's not part of the cluster
+    const SUBMITTER: &str = "127.0.0.1:9700";
+
+    struct TestNode {
+        raft: Raft,
+        blockchain: Blockchain,
+    }
+
+    /// Nodes connected by a queue of messages, the time only moves forward with `timeout`
+    struct Cluster {
+        nodes: Vec<TestNode>,
+        clock: Instant,
+        /// Answers sent to the submitter of the records
+        answers: Vec<Message>,
+    }
+
+    fn new_node(address: &str) -> TestNode {
+        let dir = std::env::temp_dir();
+        let name = address.replace(':', "_");
+        let filename = dir.join(format!("raft_test_{}", name));
+        let _ = std::fs::remove_file(&filename);
+        let args: Vec<String> = format!("--bind {} --peers {}", address, NODES.join(","))
+            .split_whitespace()
+            .map(|arg| arg.to_string())
+            .collect();
+        let config = NodeConfig::from_args(&args).unwrap();
+        let logger = Logger::new(dir.join(format!("raft_test_log_{}", name)).to_str().unwrap()).unwrap();
+        let blockchain = Blockchain::with_difficulty(0);
+        let raft = Raft::new(
+            &config,
+            RaftStore::new(filename.to_str().unwrap()),
+            &blockchain,
+            Arc::new(RwLock::new(None)),
+            Arc::new((Mutex::new(false), Condvar::new())),
+            Arc::new(logger),
+        )
+        .unwrap();
+        TestNode { raft, blockchain }
+    }
+
+    impl Cluster {
+        /// Delivers the messages until there are none left, except the ones of the disconnected nodes
+        fn deliver(&mut self, from: usize, step: Step, disconnected: &[usize]) {
+            let mut queue = VecDeque::new();
+            self.apply(from, step.committed);
+            for (to, msg) in step.messages {
+                queue.push_back((from, to, msg));
+            }
+            while let Some((from, to, msg)) = queue.pop_front() {
+                if to == SUBMITTER {
+                    self.answers.push(msg);
+                    continue;
+                }
+                let to = NODES.iter().position(|node| *node == to).unwrap();
+                if disconnected.contains(&from) || disconnected.contains(&to) {
+                    continue;
+                }
+                let sender = NODES[from].to_string();
+                let now = self.clock;
+                let node = &mut self.nodes[to];
+                let step = match msg {
+                    Message::RequestVote(request) => node.raft.handle_vote_request(sender, request, now),
+                    Message::Vote(response) => node.raft.handle_vote(sender, response, now, &node.blockchain),
+                    Message::AppendEntries(append) => node.raft.handle_append_entries(sender, *append, now),
+                    Message::AppendResponse(response) => {
+                        node.raft.handle_append_response(sender, response, &node.blockchain)
+                    }
+                    msg => panic!("Unexpected message {:?}", msg),
+                };
+                self.apply(to, step.committed);
+                for (next, msg) in step.messages {
+                    queue.push_back((to, next, msg));
+                }
+            }
+        }
+
+        fn apply(&mut self, node: usize, blocks: Vec<Block>) {
+            for block in blocks {
+                self.nodes[node].blockchain.append_block(block).unwrap();
+            }
+        }
+
+        /// The timeout of the node expires: a follower starts an election
+        /// and the leader sends its heartbeats
+        fn timeout(&mut self, node: usize, disconnected: &[usize]) {
+            self.clock += Duration::from_secs(3600);
+            let test_node = &mut self.nodes[node];
+            let step = test_node.raft.tick(self.clock, &test_node.blockchain);
+            self.deliver(node, step, disconnected);
+        }
+
+        fn propose(&mut self, leader: usize, student: &str, disconnected: &[usize]) {
+            let key = SigningKey::from_bytes(&[1; 32]);
+            let node = &mut self.nodes[leader];
+            let now = Duration::from_secs(10 + node.blockchain.len() as u64);
+            let data = RecordData::CreateStudent(student.into(), 7);
+            let mut record = Record::new(NODES[leader].into(), data, now);
+            record.sign(&key);
+            node.raft.track(record.calculate_hash(), SUBMITTER.into());
+            node.blockchain.submit_record(record).unwrap();
+            let block = node.blockchain.seal_block(10, NODES[leader].into(), now).unwrap();
+            let step = node.raft.propose(block, &node.blockchain);
+            self.deliver(leader, step, disconnected);
+            // El heartbeat avisa a los seguidores que el bloque se comprometio
+            self.timeout(leader, disconnected);
+        }
+    }
+
+    #[test]
+    fn committed_blocks_survive_a_change_of_leader() {
+        let mut cluster = Cluster {
+            nodes: NODES.iter().map(|node| new_node(node)).collect(),
+            clock: Instant::now(),
+            answers: Vec::new(),
+        };
+        cluster.timeout(0, &[]);
+        assert_eq!(cluster.nodes[0].raft.role, Role::Leader);
+
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let config = GenesisConfig::new("test".into(), key.verifying_key());
+        let leader = &mut cluster.nodes[0];
+        let genesis = leader.blockchain.seal_genesis(config, NODES[0].into(), Duration::from_secs(1), &key);
+        let step = leader.raft.propose(genesis, &leader.blockchain);
+        cluster.deliver(0, step, &[]);
+        cluster.propose(0, "100000", &[]);
+        assert!(cluster.nodes.iter().all(|node| node.blockchain.len() == 2));
+        assert!(matches!(cluster.answers.as_slice(), [Message::Ack(_)]));
+
+        // El lider aislado no llega a una mayoria, su bloque no se compromete
+        cluster.propose(0, "100001", &[0]);
+        assert_eq!(cluster.nodes[0].blockchain.len(), 2);
+        assert!(cluster.nodes[0].raft.has_uncommitted());
+        assert_eq!(cluster.answers.len(), 1);
+
+        // Un candidato sin todas las entradas de un nodo no recibe su voto
+        cluster.timeout(1, &[2]);
+        assert_eq!(cluster.nodes[1].raft.role, Role::Candidate);
+        cluster.timeout(1, &[0]);
+        assert_eq!(cluster.nodes[1].raft.role, Role::Leader);
+        cluster.propose(1, "100002", &[0]);
+        assert_eq!(cluster.nodes[2].blockchain.len(), 3);
+
+        // El lider anterior vuelve, descarta su bloque y toma el del nuevo lider
+        cluster.timeout(1, &[]);
+        let old_leader = &cluster.nodes[0];
+        assert_eq!(old_leader.raft.role, Role::Follower);
+        assert!(!old_leader.raft.has_uncommitted());
+        assert!(old_leader.blockchain.state.students.contains_key("100002"));
+        assert!(!old_leader.blockchain.state.students.contains_key("100001"));
+        // Al que envio el registro descartado se le avisa que no se agrego
+        assert!(matches!(cluster.answers.as_slice(), [Message::Ack(_), Message::Ack(_), Message::Nack(..)]));
+        let last_hash = cluster.nodes[1].blockchain.get_last_block_hash();
+        assert!(cluster.nodes.iter().all(|node| node.blockchain.get_last_block_hash() == last_hash));
+    }
+
+    #[test]
+    fn a_synced_node_votes_for_a_candidate_with_its_entries() {
+        // Otra direccion que la del cluster, los tests corren a la vez
+        let mut node = new_node("127.0.0.1:9704");
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let config = GenesisConfig::new("test".into(), key.verifying_key());
+        let genesis = node.blockchain.seal_genesis(config, NODES[1].into(), Duration::from_secs(1), &key);
+        node.blockchain.append_block(genesis).unwrap();
+        node.raft.state.current_term = 3;
+        node.raft.synced(&node.blockchain);
+        assert_eq!(node.raft.last_term(), 0);
+
+        // El candidato tiene el bloque con el term en que se agrego
+        let request = VoteRequest {
+            term: 4,
+            last_len: 1,
+            last_term: 1,
+        };
+        let step = node.raft.handle_vote_request(NODES[1].into(), request, Instant::now());
+        assert!(matches!(step.messages.as_slice(), [(_, Message::Vote(vote))] if vote.granted));
+    }
+
+    #[test]
+    fn a_shorter_blockchain_doesnt_undo_committed_entries() {
+        let mut node = new_node("127.0.0.1:9705");
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let config = GenesisConfig::new("test".into(), key.verifying_key());
+        let genesis = node.blockchain.seal_genesis(config, NODES[1].into(), Duration::from_secs(1), &key);
+        node.blockchain.append_block(genesis).unwrap();
+        node.raft.synced(&node.blockchain);
+        assert_eq!(node.raft.commit_len, 1);
+
+        node.raft.synced(&Blockchain::with_difficulty(0));
+        assert_eq!(node.raft.commit_len, 1);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::blockchain::block::Block;
+use crate::encoder::{ByteReader, ByteWriter};
+
+/// Entry of the replicated log, the index of the entry is the height of its block
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+    /// Term of the leader that added the entry to the log
+    pub term: u64,
+    pub block: Block,
+}
+
+impl LogEntry {
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.put_u64(self.term);
+        self.block.encode(writer);
+    }
+
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        Ok(LogEntry {
+            term: reader.get_u64()?,
+            block: Block::decode(reader)?,
+        })
+    }
+}
+
+/// Sent by a candidate to ask for the vote of every node
+#[derive(Clone, Debug)]
+pub struct VoteRequest {
+    pub term: u64,
+    /// Length of the log of the candidate
+    pub last_len: u64,
+    /// Term of the last entry of the log of the candidate
+    pub last_term: u64,
+}
+
+impl VoteRequest {
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.put_u64(self.term);
+        writer.put_u64(self.last_len);
+        writer.put_u64(self.last_term);
+    }
+
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        Ok(VoteRequest {
+            term: reader.get_u64()?,
+            last_len: reader.get_u64()?,
+            last_term: reader.get_u64()?,
+        })
+    }
+}
+
+/// Answer to a `VoteRequest`
+#[derive(Clone, Debug)]
+pub struct VoteResponse {
+    pub term: u64,
+    pub granted: bool,
+}
+
+impl VoteResponse {
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.put_u64(self.term);
+        writer.put_u8(self.granted as u8);
+    }
+
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        Ok(VoteResponse {
+            term: reader.get_u64()?,
+            granted: reader.get_u8()? != 0,
+        })
+    }
+}
+
+/// Sent by the leader with the entries a follower is missing,
+/// or without entries as a heartbeat
+#[derive(Clone, Debug)]
+pub struct AppendEntries {
+    pub term: u64,
+    /// Length of the log before the entries
+    pub prev_len: u64,
+    /// Term of the entry before the entries (0 if there is none)
+    pub prev_term: u64,
+    pub entries: Vec<LogEntry>,
+    /// Amount of committed entries in the log of the leader
+    pub leader_commit: u64,
+}
+
+impl AppendEntries {
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.put_u64(self.term);
+        writer.put_u64(self.prev_len);
+        writer.put_u64(self.prev_term);
+        writer.put_u32(self.entries.len() as u32);
+        for entry in &self.entries {
+            entry.encode(writer);
+        }
+        writer.put_u64(self.leader_commit);
+    }
+
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        let term = reader.get_u64()?;
+        let prev_len = reader.get_u64()?;
+        let prev_term = reader.get_u64()?;
+        let mut entries = Vec::new();
+        for _ in 0..reader.get_u32()? {
+            entries.push(LogEntry::decode(reader)?);
+        }
+        Ok(AppendEntries {
+            term,
+            prev_len,
+            prev_term,
+            entries,
+            leader_commit: reader.get_u64()?,
+        })
+    }
+}
+
+/// Answer to an `AppendEntries`
+#[derive(Clone, Debug)]
+pub struct AppendResponse {
+    pub term: u64,
+    pub success: bool,
+    /// If it succeeded, length of the log that matches the leader.
+    /// If not, length of the log the leader has to send the entries from
+    pub match_len: u64,
+}
+
+impl AppendResponse {
+    pub fn encode(&self, writer: &mut ByteWriter) {
+        writer.put_u64(self.term);
+        writer.put_u8(self.success as u8);
+        writer.put_u64(self.match_len);
+    }
+
+    pub fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        Ok(AppendResponse {
+            term: reader.get_u64()?,
+            success: reader.get_u8()? != 0,
+            match_len: reader.get_u64()?,
+        })
+    }
+}
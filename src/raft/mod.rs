@@ -0,0 +1,4 @@
+pub mod consensus;
+pub mod messages;
+pub mod store;
+pub mod timer;
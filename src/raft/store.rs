@@ -0,0 +1,145 @@
+use super::messages::LogEntry;
+use crate::blockchain::block::BLOCK_FORMAT_VERSION;
+use crate::blockchain::hash::{hash_bytes, HASH_SIZE};
+use crate::encoder::{ByteReader, ByteWriter};
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Write};
+use std::path::Path;
+
+/// Identifies the file as the state of raft
+const RAFT_MAGIC: &[u8; 4] = b"BCRF";
+
+/// State of raft that must survive a restart of the node: a node
+/// must not vote twice in the same term nor forget the entries it acknowledged.
+/// The committed entries are the blocks of the blockchain store, here
+/// only their terms are kept
+#[derive(Clone, Debug, Default)]
+pub struct PersistentState {
+    pub current_term: u64,
+
+    /// Candidate that got the vote of the node in the current term
+    pub voted_for: Option<String>,
+
+    /// `(index, term)` of the first committed entry of every term,
+    /// the committed entries before the first one have term 0
+    pub term_starts: Vec<(u64, u64)>,
+
+    /// Index of the first entry that is not committed
+    pub entries_start: u64,
+
+    /// Entries that are not committed yet
+    pub entries: Vec<LogEntry>,
+}
+
+impl PersistentState {
+    fn encode(&self, writer: &mut ByteWriter) {
+        writer.put_u64(self.current_term);
+        match &self.voted_for {
+            Some(candidate) => {
+                writer.put_u8(1);
+                writer.put_str(candidate);
+            }
+            None => writer.put_u8(0),
+        }
+        writer.put_u32(self.term_starts.len() as u32);
+        for (index, term) in &self.term_starts {
+            writer.put_u64(*index);
+            writer.put_u64(*term);
+        }
+        writer.put_u64(self.entries_start);
+        writer.put_u32(self.entries.len() as u32);
+        for entry in &self.entries {
+            entry.encode(writer);
+        }
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, String> {
+        let current_term = reader.get_u64()?;
+        let voted_for = match reader.get_u8()? {
+            0 => None,
+            1 => Some(reader.get_str()?),
+            tag => return Err(format!("Invalid vote tag {}", tag)),
+        };
+        let mut term_starts = Vec::new();
+        for _ in 0..reader.get_u32()? {
+            term_starts.push((reader.get_u64()?, reader.get_u64()?));
+        }
+        let entries_start = reader.get_u64()?;
+        let mut entries = Vec::new();
+        for _ in 0..reader.get_u32()? {
+            entries.push(LogEntry::decode(reader)?);
+        }
+        Ok(PersistentState {
+            current_term,
+            voted_for,
+            term_starts,
+            entries_start,
+            entries,
+        })
+    }
+}
+
+/// File with the persistent state of raft.
+/// The file is `[magic][block format version: u32][checksum][state]`,
+/// it's written in a temporary file that then replaces the old one
+#[derive(Debug)]
+pub struct RaftStore {
+    filename: String,
+}
+
+impl RaftStore {
+    pub fn new(filename: &str) -> Self {
+        RaftStore {
+            filename: filename.to_string(),
+        }
+    }
+
+    /// Reads the saved state, the default state if the node never saved one
+    pub fn load(&self) -> Result<PersistentState, Error> {
+        if !Path::new(&self.filename).exists() {
+            return Ok(PersistentState::default());
+        }
+        let data = fs::read(&self.filename)?;
+        let invalid = |reason: String| Error::new(ErrorKind::InvalidData, reason);
+
+        let header = store_header();
+        if data.len() < header.len() + HASH_SIZE || data[..header.len()] != header[..] {
+            return Err(invalid(format!(
+                "not a raft state of version {}",
+                BLOCK_FORMAT_VERSION
+            )));
+        }
+        let payload = &data[header.len() + HASH_SIZE..];
+        if hash_bytes(payload).as_bytes()[..] != data[header.len()..header.len() + HASH_SIZE] {
+            return Err(invalid("the checksum of the raft state doesn't match".to_string()));
+        }
+        let mut reader = ByteReader::new(payload);
+        let state = PersistentState::decode(&mut reader).map_err(invalid)?;
+        if !reader.is_empty() {
+            return Err(invalid("unexpected bytes at the end of the raft state".to_string()));
+        }
+        Ok(state)
+    }
+
+    /// Replaces the saved state and waits until it reaches the disk
+    pub fn save(&self, state: &PersistentState) -> Result<(), Error> {
+        let mut writer = ByteWriter::new();
+        state.encode(&mut writer);
+        let payload = writer.into_bytes();
+        let mut data = store_header();
+        data.extend_from_slice(hash_bytes(&payload).as_bytes());
+        data.extend(payload);
+
+        let tmp_filename = format!("{}.tmp", self.filename);
+        let mut file = File::create(&tmp_filename)?;
+        file.write_all(&data)?;
+        file.sync_data()?;
+        fs::rename(tmp_filename, &self.filename)
+    }
+}
+
+fn store_header() -> Vec<u8> {
+    let mut header = RAFT_MAGIC.to_vec();
+    header.extend_from_slice(&BLOCK_FORMAT_VERSION.to_be_bytes());
+    header
+}
@@ -0,0 +1,51 @@
+use super::consensus::Raft;
+use crate::blockchain::blockchain::Blockchain;
+use crate::utils::socket::Socket;
+
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time between the checks of the timeouts of raft
+const TICK_MILLIS: u64 = 50;
+
+/// Responsible for the timeouts of the raft mode: starts an election when
+/// the leader doesn't send anything for a while and sends the heartbeats
+/// of the leader. It replaces the leader discoverer, the bully election
+/// and the heartbeat monitor
+pub struct RaftTimer {
+    pub raft: Arc<Mutex<Raft>>,
+    pub blockchain: Arc<RwLock<Blockchain>>,
+    pub socket: Socket,
+    pub alive: Arc<RwLock<bool>>,
+}
+
+impl RaftTimer {
+    pub fn new(
+        raft: Arc<Mutex<Raft>>,
+        blockchain: Arc<RwLock<Blockchain>>,
+        socket: Socket,
+        alive: Arc<RwLock<bool>>,
+    ) -> Self {
+        RaftTimer {
+            raft,
+            blockchain,
+            socket,
+            alive,
+        }
+    }
+
+    pub fn run(&mut self) {
+        while *self.alive.read().unwrap() {
+            thread::sleep(Duration::from_millis(TICK_MILLIS));
+            let step = {
+                let mut raft = self.raft.lock().unwrap();
+                let blockchain = self.blockchain.read().unwrap();
+                raft.tick(Instant::now(), &blockchain)
+            };
+            for (node, msg) in step.messages {
+                self.socket.send_to(msg, node);
+            }
+        }
+    }
+}
@@ -1,127 +1,162 @@
-use crate::utils::messages::*;
+use crate::utils::messages::{LeaderResponse, Message};
 use std::io::{self, BufRead};
 use std::option::Option;
 use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::time::{Duration, SystemTime};
 
+use crate::blockchain::hash::BlockHash;
 use crate::blockchain::blockchain::Blockchain;
+use crate::blockchain::record::{Record, RecordData};
+use crate::blockchain::signature::{parse_public_key, public_key_to_hex, KeyFingerprint};
+use crate::blockchain::state_machine::StateMachine;
+use ed25519_dalek::{SigningKey, VerifyingKey};
 use crate::utils::logger::Logger;
 use crate::utils::socket::Socket;
 
 const ACK_TIMEOUT_SECS: u64 = 2;
+/// In raft mode the leader answers when the block of the record is committed
+const COMMIT_TIMEOUT_SECS: u64 = 10;
 const WAITING_FOR_LOCK_ACQUIRED_TIMEOUT: u64 = 15;
 
+/// Action chosen by the user in the menu
+enum UserCommand {
+    AddRecord(Box<RecordData>),
+    Close,
+}
+
+/// State shared with the node to talk with the leader
+pub struct LeaderHandles {
+    /// Notified when the leader is found
+    pub leader_condvar: Arc<(Mutex<bool>, Condvar)>,
+    pub leader_addr: Arc<RwLock<Option<String>>>,
+
+    /// Answer of the leader to the last record sent
+    pub msg_ack_cv: Arc<(Mutex<LeaderResponse>, Condvar)>,
+
+    /// Set when the leader doesn't answer
+    pub leader_down_cv: Arc<(Mutex<bool>, Condvar)>,
+
+    /// Set when the leader gives the distributed mutex to this node
+    pub lock_acquired: Arc<(Mutex<bool>, Condvar)>,
+}
+
 /// Responsible for read msg from stdin with diferent options
 /// 
 pub struct StdinReader {
-    leader_condvar: Arc<(Mutex<bool>, Condvar)>,
+    leader: LeaderHandles,
     socket: Socket,
-    leader_addr: Arc<RwLock<Option<String>>>,
     node_alive: Arc<RwLock<bool>>,
-    msg_ack_cv: Arc<(Mutex<bool>, Condvar)>,
-    leader_down_cv: Arc<(Mutex<bool>, Condvar)>,
-    lock_acquired: Arc<(Mutex<bool>, Condvar)>,
     blockchain: Arc<RwLock<Blockchain>>,
-    blockchain_logger: Arc<Logger>
+    blockchain_logger: Arc<Logger>,
+    signing_key: Arc<SigningKey>,
+
+    // En modo raft el lider ordena los registros, no se usa el mutex distribuido
+    use_mutex: bool,
 }
 
 impl StdinReader {
     pub fn new(
-        leader_condvar: Arc<(Mutex<bool>, Condvar)>,
+        leader: LeaderHandles,
         socket: Socket,
-        leader_addr: Arc<RwLock<Option<String>>>,
         node_alive: Arc<RwLock<bool>>,
-        msg_ack_cv: Arc<(Mutex<bool>, Condvar)>,
-        leader_down_cv: Arc<(Mutex<bool>, Condvar)>,
-        lock_acquired: Arc<(Mutex<bool>, Condvar)>,
         blockchain: Arc<RwLock<Blockchain>>,
-        blockchain_logger: Arc<Logger>
+        blockchain_logger: Arc<Logger>,
+        signing_key: Arc<SigningKey>,
+        use_mutex: bool,
     ) -> Self {
         StdinReader {
-            leader_condvar,
+            leader,
             socket,
-            leader_addr,
             node_alive,
-            msg_ack_cv,
-            leader_down_cv,
-            lock_acquired,
             blockchain,
-            blockchain_logger
+            blockchain_logger,
+            signing_key,
+            use_mutex,
         }
     }
 
     /// Print menu string with options
     fn menu(&self) {
-        println!("Select an option:\n\t1. Add block\n\t2. Print Blockchain\n\t3. Exit");
+        println!(
+            "Select an option:\n\t1. Add student\n\t2. Update qualification\n\t3. Remove student\
+            \n\t4. Add subject grade\n\t5. Transfer student\n\t6. Show student history\
+            \n\t7. Show grade certificate\n\t8. Print Blockchain\n\t9. Add admin\
+            \n\t10. Grant permission\n\t11. Revoke permission\n\t12. Exit"
+        );
     }
 
     /// Await until leader is set and read from stdin
     /// If the msg is a new block adquire mutex and 
     /// sent that msg to leader addr if the mutex is taken
-    /// Sent the block and realese mutex
+    /// Sent the block and realese mutex.
+    /// In raft mode the record is sent without the mutex
     pub fn run(&mut self) {
         loop {
             self.wait_for_leader();
-            let value = self.read_option();
-            if &value == "" {
+            let record_data = match self.read_option() {
+                None => continue,
+                Some(UserCommand::AddRecord(record_data)) => *record_data,
+                Some(UserCommand::Close) => {
+                    let mut guard = self.node_alive.write().unwrap();
+                    *guard = false;
+                    let me = self.socket.local_addr().to_string();
+                    self.socket.send_to(Message::Noop, me);
+                    break;
+                }
+            };
+
+            let addr = self.leader.leader_addr.read().unwrap().clone();
+            if addr.is_none() {
                 continue;
             }
-            if &value == CLOSE {
-                let mut guard = self.node_alive.write().unwrap();
-                *guard = false;
-                let me = self.socket.local_addr().to_string();
-                self.socket.send_to(NOOP_MSG.to_string(), me).unwrap();
-                break;
-            }
 
-            let addr = self.leader_addr.read().unwrap().clone();
-            if addr.is_none() {
+            if self.use_mutex && !self.acquire_lock(addr.clone().unwrap()) {
                 continue;
             }
 
-            // Tomamos el lock del leader
-            self.socket
-                .send_to(ACQUIRE_MSG.to_string(), addr.clone().unwrap())
-                .unwrap();
-
-            // Asumimos que no hay congestion mas de WAITING_FOR_LOCK_ACQUIRED_TIMEOUT
-            // Esperamos en la condvar hasta recibir un mensaje de LOCK_AQUIRED
-            {
-                let (lock, cvar) = &*self.lock_acquired;
-                let guard  = lock.lock().unwrap();
-                let timeout = Duration::from_secs(WAITING_FOR_LOCK_ACQUIRED_TIMEOUT);
-                let result = cvar
-                    .wait_timeout_while(guard, timeout, |&mut lock_acquired| !lock_acquired)
-                    .unwrap();
-
-                if result.1.timed_out() {
-                    // El lider no nos dió el lock en WAITING_FOR_LOCK_ACQUIRED_TIMEOUT
-                    // puede estar caído o simplemente hay mucha congestión.
-                    println!("El lider se ha caido. Vuelva a reintentar si había agregado algo a la blockchain.");
-                    self.set_leader_down();
-                    continue;
-                }
+            let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
+            let mut record = Record::new(self.socket.local_addr().to_string(), record_data, now);
+            record.sign(&self.signing_key);
+            let hash = record.calculate_hash();
+            // La respuesta de un registro anterior que llegue tarde no es la de este
+            *self.leader.msg_ack_cv.0.lock().unwrap() = None;
+            self.socket.send_to(Message::Record(Box::new(record)), addr.clone().unwrap());
+
+            self.wait_for_ack(hash);
+
+            if self.use_mutex {
+                self.socket
+                    .send_to(Message::Release, addr.clone().unwrap());
             }
-            
-            let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
-            let mut data_to_send = String::new();
-            data_to_send.push_str(
-                &(format!(
-                    "{},{},{}",
-                    &value,
-                    &now,
-                    &self.socket.local_addr().to_string()
-                ))
-            );
-            // Nos dieron el lock
-            self.socket.send_to(data_to_send, addr.clone().unwrap()).unwrap();
+        }
+    }
 
-            self.wait_for_ack();
+    /// Asks the leader for the distributed mutex and waits until it's given,
+    /// returns false if the leader didn't answer
+    fn acquire_lock(&mut self, leader: String) -> bool {
+        // Tomamos el lock del leader
+        self.socket
+            .send_to(Message::Acquire, leader);
 
-            self.socket
-                .send_to(RELEASE_MSG.to_string(), addr.clone().unwrap())
-                .unwrap();
+        // Asumimos que no hay congestion mas de WAITING_FOR_LOCK_ACQUIRED_TIMEOUT
+        // Esperamos en la condvar hasta recibir un mensaje de LOCK_AQUIRED
+        let (lock, cvar) = &*self.leader.lock_acquired;
+        let guard  = lock.lock().unwrap();
+        let timeout = Duration::from_secs(WAITING_FOR_LOCK_ACQUIRED_TIMEOUT);
+        let (mut lock_acquired, result) = cvar
+            .wait_timeout_while(guard, timeout, |&mut lock_acquired| !lock_acquired)
+            .unwrap();
+        // Se limpia para esperar el LOCK_ACQUIRED del proximo registro
+        *lock_acquired = false;
+
+        if result.timed_out() {
+            // El lider no nos dió el lock en WAITING_FOR_LOCK_ACQUIRED_TIMEOUT
+            // puede estar caído o simplemente hay mucha congestión.
+            println!("El lider se ha caido. Vuelva a reintentar si había agregado algo a la blockchain.");
+            self.set_leader_down();
+            return false;
         }
+        true
     }
 
     /// Read a new line from stdin
@@ -132,40 +167,174 @@ impl StdinReader {
         line
     }
 
-    /// If option to add new block was choseen 
-    /// then read again from stdin and return value if is valis
-    fn option_add_block(&mut self) -> String {
-        println!("Write a block (id,qualification): ");
-        let line = self.read();
-        let student_data: Vec<&str> = line.split(",").collect();
-        if student_data.len() != 2 {
-            println!("Unsupported data format, usage: id, qualification");
-            return String::new();
+    /// Print the name of a field and read its value from stdin
+    fn read_field(&self, name: &str) -> String {
+        println!("{}: ", name);
+        self.read().trim().to_string()
+    }
+
+    /// Read a field that has to be a number
+    fn read_number_field(&self, name: &str) -> Option<i32> {
+        match self.read_field(name).parse::<i32>() {
+            Ok(number) => Some(number),
+            Err(_) => {
+                println!("The {} must be a number", name.to_lowercase());
+                None
+            }
+        }
+    }
+
+    /// Read a student id, it can't be empty
+    fn read_student_id(&self, name: &str) -> Option<String> {
+        let id = self.read_field(name);
+        if id.is_empty() {
+            println!("The {} can't be empty", name.to_lowercase());
+            return None;
+        }
+        Some(id)
+    }
+
+    /// Read a public key in hex, an empty line is the key of this node
+    fn read_public_key(&self, name: &str) -> Option<VerifyingKey> {
+        let hex = self.read_field(&format!("{} (empty for this node)", name));
+        if hex.is_empty() {
+            return Some(self.signing_key.verifying_key());
+        }
+        match parse_public_key(&hex) {
+            Ok(key) => Some(key),
+            Err(err) => {
+                println!("{}", err);
+                None
+            }
         }
-        return line.to_string();
+    }
+
+    /// Read the fields of a change of permissions chosen in the menu,
+    /// an empty subject means every subject
+    fn option_change_permissions(&mut self, option: &str) -> Option<UserCommand> {
+        let key = self.read_public_key("Public key")?;
+        let record_data = match option {
+            "9" => RecordData::AddAdmin(key),
+            _ => {
+                let subject = self.read_field("Subject (empty for all subjects)");
+                let subject = if subject.is_empty() { None } else { Some(subject) };
+                if option == "10" {
+                    RecordData::GrantPermission(key, subject)
+                } else {
+                    RecordData::RevokePermission(key, subject)
+                }
+            }
+        };
+        Some(UserCommand::AddRecord(Box::new(record_data)))
+    }
+
+    /// Read the fields of the operation chosen in the menu
+    /// (one field per line so the values may contain any character)
+    fn option_add_record(&mut self, option: &str) -> Option<UserCommand> {
+        let id = self.read_student_id("Student id")?;
+        let record_data = match option {
+            "1" => RecordData::CreateStudent(id, self.read_number_field("Qualification")?),
+            "2" => RecordData::UpdateQualification(id, self.read_number_field("Qualification")?),
+            "3" => RecordData::RemoveStudent(id),
+            "4" => {
+                let subject = self.read_field("Subject");
+                RecordData::AddSubjectGrade(id, subject, self.read_number_field("Grade")?)
+            }
+            _ => RecordData::TransferStudent(id, self.read_student_id("New student id")?),
+        };
+        Some(UserCommand::AddRecord(Box::new(record_data)))
     }
 
     /// Read Menu option input from stdin
-    fn read_option(&mut self) -> String {
+    fn read_option(&mut self) -> Option<UserCommand> {
         self.menu();
         let option = self.read();
 
         match option.as_str() {
-            "1" => return self.option_add_block(),
-            "2" => self.option_show_blockchain(),
-            "3" => return CLOSE.to_string(),
+            "1" | "2" | "3" | "4" | "5" => return self.option_add_record(&option),
+            "6" => self.option_show_history(),
+            "7" => self.option_show_certificate(),
+            "8" => self.option_show_blockchain(),
+            "9" | "10" | "11" => return self.option_change_permissions(&option),
+            "12" => return Some(UserCommand::Close),
             _ => {
                 println!("Invalid option, choose again...")
             }
         }; 
 
-        return String::new();
+        None
+    }
+
+    /// Print every change of a student in stdout if option 6 was choseen
+    fn option_show_history(&self) {
+        let id = match self.read_student_id("Student id") {
+            Some(id) => id,
+            None => return,
+        };
+        let history = self.blockchain.read().unwrap().get_student_history(&id);
+        if history.is_empty() {
+            println!("There are no records of student {}", id);
+        }
+        for entry in history {
+            println!("{}", entry);
+        }
     }
 
-    /// Print blockchain in stdout if option 2 was choseen
+    /// Print the proof that the last change of a student is in the blockchain
+    /// (the record, its block and the Merkle path) if option 7 was choseen
+    fn option_show_certificate(&self) {
+        let id = match self.read_student_id("Student id") {
+            Some(id) => id,
+            None => return,
+        };
+        let blockchain = self.blockchain.read().unwrap();
+        let entry = match blockchain.get_student_history(&id).pop() {
+            Some(entry) => entry,
+            None => {
+                println!("There are no records of student {}", id);
+                return;
+            }
+        };
+        let block = blockchain.get_block(entry.block_index).unwrap();
+        let record = &block.records[entry.record_index];
+        let proof = block.inclusion_proof(entry.record_index).unwrap();
+
+        println!("Certificate of student {}:\n{}", id, entry);
+        if let Some(signature) = &record.signature {
+            println!(
+                "Signed by: {} (valid: {})",
+                KeyFingerprint(&signature.public_key),
+                record.verify_signature().is_ok()
+            );
+        }
+        println!("Merkle root: {}", block.header.merkle_root);
+        println!("Proof: {}", proof);
+        println!("Verified: {}", proof.verify(record, &block.header.merkle_root));
+    }
+
+    /// Print blockchain in stdout if option 8 was choseen
     fn option_show_blockchain(&self) {
         let blockchain = self.blockchain.read().unwrap().clone();
+        if let Some(genesis) = &blockchain.state.genesis {
+            println!("{}", genesis);
+        }
         println!("{}", blockchain);
+        println!("{}", blockchain.state.permissions);
+        println!("State root: {}", blockchain.state.state_root());
+        println!(
+            "Public key of this node: {}",
+            public_key_to_hex(&self.signing_key.verifying_key())
+        );
+        let pending = blockchain.get_pending_records();
+        if !pending.is_empty() {
+            println!("Pending records: {}", pending.len());
+        }
+        if blockchain.first_height() > 0 {
+            println!("Synced from the snapshot of block {}", blockchain.first_height() - 1);
+        }
+        if let Err(err) = blockchain.validate() {
+            println!("The local blockchain is corrupted! {}", err);
+        }
         
         for block in blockchain.blocks {
             self.blockchain_logger.info(format!("{:#?}\n", block));
@@ -175,7 +344,7 @@ impl StdinReader {
     /// Await for leadr addr is set 
     /// Is notificated with a leader condvar
     fn wait_for_leader(&self) {
-        let (lock, cv) = &*self.leader_condvar;
+        let (lock, cv) = &*self.leader.leader_condvar;
 
         let mut leader_found = lock.lock().unwrap();
 
@@ -184,29 +353,36 @@ impl StdinReader {
         }
     }
 
-    /// Whait for leader ack msg to check if the leader is down
-    fn wait_for_ack(&self) {
-        let (lock, cv) = &*self.msg_ack_cv;
-        let mut guard = lock.lock().unwrap();
+    /// Whait for leader ack or nack msg to check if the leader is down.
+    /// If the leader rejected the record the reason is shown to the user.
+    /// The answers to other records (that arrived late) are ignored
+    fn wait_for_ack(&self, hash: BlockHash) {
+        let (lock, cv) = &*self.leader.msg_ack_cv;
+        let guard = lock.lock().unwrap();
+        let timeout = if self.use_mutex { ACK_TIMEOUT_SECS } else { COMMIT_TIMEOUT_SECS };
 
         // TODO: Si esperar el ack nos da timeout es porque el lider
         // esta caido. Esperar a que se setee el nuevo lider y reintentar
-        //TODO. Agregar guard para los spurious wake up
-        let result = cv
-            .wait_timeout(guard, Duration::from_secs(ACK_TIMEOUT_SECS))
+        let (mut guard, _) = cv
+            .wait_timeout_while(guard, Duration::from_secs(timeout), |response| {
+                !matches!(response, Some((answered, _)) if *answered == hash)
+            })
             .unwrap();
 
-        guard = result.0;
-
-        if !*guard { self.set_leader_down() }
-
-        *guard = false;
+        match guard.take().filter(|(answered, _)| *answered == hash).map(|(_, response)| response) {
+            None if !self.use_mutex => {
+                println!("The leader didn't confirm the record, it may not have been added")
+            }
+            None => self.set_leader_down(),
+            Some(Ok(())) => {}
+            Some(Err(reason)) => println!("The record was rejected by the leader: {}", reason),
+        }
     }
 
     /// If found that the leader is down change
     ///  value of condvar and notify all nodes
     fn set_leader_down(&self) {
-        let (lock_leader_down, cv_leader_down) = &*self.leader_down_cv;
+        let (lock_leader_down, cv_leader_down) = &*self.leader.leader_down_cv;
         *lock_leader_down.lock().unwrap() = true;
         cv_leader_down.notify_all();
     }
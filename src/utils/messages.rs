@@ -1,16 +1,63 @@
-pub const END: &str = "-";
-pub const ACQUIRE_MSG: &str = "acquire";
-pub const RELEASE_MSG: &str = "release";
-pub const BLOCKCHAIN: &str = "blockchain";
-pub const CLOSE: &str = "close";
-pub const WHO_IS_LEADER: &str = "who_is_leader";
-pub const NOOP_MSG: &str = "no_op";
+use crate::blockchain::block::Block;
+use crate::blockchain::hash::BlockHash;
+use crate::blockchain::record::Record;
+use crate::raft::messages::{AppendEntries, AppendResponse, VoteRequest, VoteResponse};
 
-// Mensaje devuelto por el lider cuando esta ok el recibo del dato
-pub const ACK_MSG: &str = "ack";
+/// Messages exchanged between nodes
+#[derive(Clone, Debug)]
+pub enum Message {
+    /// Asks the leader for the distributed mutex
+    Acquire,
+    /// Gives the distributed mutex back to the leader
+    Release,
+    /// Sent by the leader when the mutex was given to the node
+    LockAcquired,
+    /// Sent by a new node to find out who the leader is
+    WhoIsLeader,
+    /// Starts the transfer of the whole blockchain
+    Blockchain,
+    /// Part of the serialized snapshot the transferred blockchain starts from,
+    /// it's sent before the blocks after the snapshot (index, total parts, bytes)
+    SnapshotChunk(u32, u32, Vec<u8>),
+    /// Ends the transfer of the whole blockchain
+    End,
+    /// Message sent to itself to wake up the node
+    Noop,
+    // Mensaje devuelto por el lider cuando esta ok el recibo del dato, con el hash del registro
+    Ack(BlockHash),
+    // Mensaje devuelto por el lider cuando rechaza el dato, con el hash del registro y el motivo
+    Nack(BlockHash, String),
+    // Bully related msgs, con el term de la eleccion
+    Election(u64),
+    Coordinator(u64),
+    Ok(u64),
+    /// A record to be added to the blockchain
+    Record(Box<Record>),
+    /// A block sealed by the leader (with its hashes and all its records)
+    Block(Box<Block>),
+    /// Sent to the leader by a node that joins the cluster
+    Join,
+    /// Sent to the leader by a node that leaves the cluster,
+    /// or to every node by a leader that leaves
+    Leave,
+    /// Every node of the cluster, sent by the leader when a node joins or leaves
+    Members(Vec<String>),
+    /// Sent periodically by the leader to every node, so they know it's alive.
+    /// A node that suspects the leader is down sends it one, the leader answers with `Coordinator`
+    Heartbeat,
+    /// Answer of a node to the heartbeat of the leader, the leader removes
+    /// the members that stop answering
+    HeartbeatAck,
+    /// Raft: a candidate asks for the vote of the node
+    RequestVote(VoteRequest),
+    /// Raft: answer to `RequestVote`
+    Vote(VoteResponse),
+    /// Raft: the leader sends the entries the node is missing, or a heartbeat
+    AppendEntries(Box<AppendEntries>),
+    /// Raft: answer to `AppendEntries`
+    AppendResponse(AppendResponse),
+}
 
-// Bully related msgs
-pub const ELECTION: &str = "election";
-pub const COORDINATOR: &str = "coordinator";
-pub const OK: &str = "ok";
-pub const LOCK_ACQUIRED: &str = "lock_acquired";
+/// Answer of the leader to a submitted record and the hash of the record,
+/// None while it didn't answer and Err with the reason if the record was rejected
+pub type LeaderResponse = Option<(BlockHash, Result<(), String>)>;
@@ -1,34 +1,49 @@
 use std::net::{SocketAddr, UdpSocket};
 
 use crate::encoder::{decode_from_bytes, encode_to_bytes};
+use crate::utils::logger::Logger;
+use crate::utils::messages::Message;
+use std::sync::Arc;
 
-const RECV_BUF_SIZE: usize = 128;
+// Tamaño maximo de un datagrama UDP
+const RECV_BUF_SIZE: usize = 65507;
 
 pub struct Socket {
     socket: UdpSocket,
+
+    // Cluster al que pertenece el nodo, va en cada mensaje
+    cluster_id: String,
+
+    logger: Arc<Logger>,
 }
 
 impl Socket {
-    pub fn new(socket: UdpSocket) -> Self {
-        Socket { socket }
+    pub fn new(socket: UdpSocket, cluster_id: String, logger: Arc<Logger>) -> Self {
+        Socket { socket, cluster_id, logger }
     }
 
     pub fn try_clone(&mut self) -> Socket {
         let clone = self.socket.try_clone().unwrap();
-        Socket::new(clone)
+        Socket::new(clone, self.cluster_id.clone(), self.logger.clone())
     }
 
-    pub fn send_to(&mut self, msg: String, addr: String) -> Result<usize, std::io::Error> {
-        self.socket.send_to(&encode_to_bytes(msg.as_str()), addr)
+    /// Sends the message to `addr`. If it can't be sent (e.g. a remote peer
+    /// is unreachable) the error is logged and the message is lost, like a
+    /// datagram lost on the way
+    pub fn send_to(&mut self, msg: Message, addr: String) {
+        if let Err(err) = self.socket.send_to(&encode_to_bytes(&msg, &self.cluster_id), &addr) {
+            self.logger.info(format!("Unable to send {:?} to {}: {}", msg, addr, err));
+        }
     }
 
     pub fn local_addr(&mut self) -> SocketAddr {
         self.socket.local_addr().unwrap()
     }
 
-    pub fn recv_from(&mut self) -> (usize, SocketAddr, String) {
-        let mut buf = [0; RECV_BUF_SIZE];
+    /// Receives the next message, the message is an error if it couldn't be decoded
+    pub fn recv_from(&mut self) -> (usize, SocketAddr, Result<Message, String>) {
+        let mut buf = vec![0; RECV_BUF_SIZE];
         let (size, from) = self.socket.recv_from(&mut buf).unwrap();
-        (size, from, decode_from_bytes(buf.to_vec()))
+        (size, from, decode_from_bytes(&buf[..size], &self.cluster_id))
     }
 }